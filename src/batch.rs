@@ -0,0 +1,102 @@
+use anyhow::{anyhow, bail, ensure, Context};
+use bitcoin::secp256k1::All;
+use clap::Parser;
+
+use crate::{
+    args::{self, Cli},
+    json::Value,
+    tx::TxBuilder,
+};
+
+/// Builds and signs a transaction for each spec in a JSON array, returning a JSON array of their
+/// hex, or of `{"error": ...}` for the specs that failed so that one bad spec doesn't cost the
+/// others
+pub fn build(input: &str) -> Result<String, anyhow::Error> {
+    let Value::Array(specs) = Value::parse(input).context("invalid batch")? else {
+        bail!("the batch must be a JSON array of specs");
+    };
+    let results = specs
+        .into_iter()
+        .map(|spec| match build_one(spec) {
+            Ok(hex) => Value::String(hex),
+            Err(e) => Value::Object(vec![(
+                "error".to_string(),
+                Value::String(format!("{:#}", e)),
+            )]),
+        })
+        .collect();
+    Ok(Value::Array(results).to_string())
+}
+
+/// Builds the spec the way the command line would with the spec as its `--config`, leaving out
+/// what needs the network
+fn build_one(spec: Value) -> Result<String, anyhow::Error> {
+    let Value::Object(fields) = spec else {
+        bail!("must be a JSON object");
+    };
+    let mut argv = vec![env!("CARGO_PKG_NAME").to_string()];
+    argv.extend(args::field_args(fields, &[])?);
+    let Some(mut args) = Cli::try_parse_from(argv)
+        // Without the usage clap appends, which would repeat in every failed entry
+        .map_err(|e| {
+            let message = e.to_string();
+            let message = message.split("\n\nUsage:").next().unwrap_or_default();
+            anyhow!("{}", message.trim().trim_start_matches("error: "))
+        })?
+        .args
+    else {
+        bail!("must be the arguments of a transaction");
+    };
+    ensure!(
+        !args.testnet_faucet_spend,
+        "`--testnet-faucet-spend` needs the network, which a batch doesn't use"
+    );
+    args.load_private_key()?;
+    args.validate()?;
+
+    let mut tx_builder = TxBuilder::<All>::new(&args)?;
+    let tx = tx_builder.create_without_sig()?.sign()?.verify()?.build()?;
+    Ok(tx.hex())
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    fn test_build() {
+        let spec = |send_amount: u64| {
+            format!(
+                r#"{{
+                    "source_address": "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx",
+                    "destination_address": "mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn",
+                    "private_key": "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP",
+                    "send_amount": {},
+                    "utxo_txid": "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331",
+                    "utxo_tx_index": 1,
+                    "utxo_amount": 4847873,
+                    "utxo_script_pubkey": "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac"
+                }}"#,
+                send_amount
+            )
+        };
+        let input = format!("[{}, {}]", spec(100), spec(4847873));
+        let output = Value::parse(&build(&input).unwrap()).unwrap();
+
+        let results = output.as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        let hex = results[0].as_str().unwrap();
+        assert!(hex.starts_with("0100000001") || hex.starts_with("0200000001"));
+        assert_eq!(
+            results[1].get("error").and_then(Value::as_str),
+            Some("insufficient funds: 4848873 satoshi needed, 4847873 satoshi available (1000 satoshi short)")
+        );
+
+        assert_eq!(
+            build("{}").unwrap_err().to_string(),
+            "the batch must be a JSON array of specs"
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::anyhow;
+
+use crate::{
+    http::{self, Failure, RetryPolicy},
+    tx,
+};
+
+/// Submits a raw transaction (hex) to an Esplora/Blockstream API at `url`, returning the txid
+pub fn broadcast(
+    url: &str,
+    raw_hex: &str,
+    retry_policy: RetryPolicy,
+) -> Result<String, anyhow::Error> {
+    let url = format!("{}/tx", url.trim_end_matches('/'));
+    let mut retried = false;
+
+    retry_policy.run(|timeout| {
+        let response = http::post(&url, "text/plain", raw_hex, timeout)
+            .map_err(|e| e.context("broadcast failed"))?;
+        let resubmitted = std::mem::replace(&mut retried, true);
+        match response.status {
+            200..=299 => Ok(response.body.trim().to_string()),
+            // A failed attempt may still have reached the node, e.g. when the response timed out,
+            // in which case the retry finds the transaction already in its mempool or chain
+            400..=499 if resubmitted && response.body.contains("already") => {
+                let txid = tx::decode_transaction(raw_hex).map(|tx| tx.txid().to_string());
+                txid.map_err(|e| Failure::Permanent(e.context("broadcast failed")))
+            }
+            // The body carries the node's reason, e.g. `bad-txns-inputs-missingorspent`
+            status => {
+                let error = anyhow!(
+                    "broadcast rejected with HTTP status {}: {}",
+                    status,
+                    response.body.trim()
+                );
+                Err(match status {
+                    500.. => Failure::Transient(error),
+                    _ => Failure::Permanent(error),
+                })
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rstest::*;
+
+    use super::*;
+
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy {
+            retries: 0,
+            timeout: Duration::from_secs(5),
+            base_delay: Duration::from_millis(1),
+        }
+    }
+
+    #[rstest]
+    fn test_broadcast() {
+        let txid = "6228dedb6c7f743114093934fce3577013d0673b9688ac1df30a942ca0bd999f";
+        let (url, handle) = http::serve_once(200, txid);
+        let result = broadcast(&format!("{}/testnet/api", url), "0100", retry_policy()).unwrap();
+        let request = handle.join().unwrap();
+
+        assert_eq!(result, txid);
+        assert!(request.starts_with("POST /testnet/api/tx HTTP/1.1\r\n"));
+        assert!(request.ends_with("\r\n\r\n0100"));
+    }
+
+    #[rstest]
+    #[case(
+        0,
+        Err("broadcast rejected with HTTP status 400: txn-already-in-mempool")
+    )]
+    #[case(
+        1,
+        Ok("43969cd14e454f77e69820a04d0e194dca7856591f84689cc3f73acdfc654976")
+    )]
+    fn test_broadcast_resubmitted(#[case] retries: u32, #[case] expected: Result<&str, &str>) {
+        // The transaction the sample arguments build
+        let raw_hex = "010000000131c3a964f8a8176863a122142ca7db8c173769175aaefbb5160359ada9be3ed7010000006a4730440220644f271a0da965c8a68db74c51f1f66be93f3ea51fb9c443dd20d4857637de3402203c736aafa921dd44ab01360fcf0dbe7443fdad8fec95751be5732211294c5ac701210303998660a6a026b2f8aa72d37a077b6a76b282b2d5b73fc582fdc274f66fa5bcffffffff0264000000000000001976a914a997f6d478624028ea1f36082e7ceb5d79d7567188acb5f44900000000001976a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac00000000";
+        let mut responses = vec![(503, "busy"), (400, "txn-already-in-mempool")];
+        responses.drain(..1 - retries as usize);
+        let (url, handle) = http::serve(responses);
+        let result = broadcast(
+            &url,
+            raw_hex,
+            RetryPolicy {
+                retries,
+                ..retry_policy()
+            },
+        );
+        handle.join().unwrap();
+
+        assert_eq!(
+            result.map_err(|e| e.to_string()),
+            expected.map(str::to_string).map_err(str::to_string)
+        );
+    }
+
+    #[rstest]
+    fn test_broadcast_rejected() {
+        let (url, handle) = http::serve_once(
+            400,
+            "sendrawtransaction RPC error: {\"code\":-25,\"message\":\"bad-txns-inputs-missingorspent\"}",
+        );
+        let error = broadcast(&url, "0100", retry_policy()).unwrap_err();
+        handle.join().unwrap();
+
+        assert_eq!(
+            error.to_string(),
+            "broadcast rejected with HTTP status 400: sendrawtransaction RPC error: \
+             {\"code\":-25,\"message\":\"bad-txns-inputs-missingorspent\"}"
+        );
+    }
+}
@@ -0,0 +1,362 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, ensure, Context};
+use bitcoin::{
+    bip32::{ChildNumber, DerivationPath, ExtendedPrivKey},
+    secp256k1::Secp256k1,
+    PrivateKey, ScriptBuf,
+};
+
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Single-key output descriptor that can be signed for, e.g. `pkh(tprv.../44'/1'/0'/0/*)`
+#[derive(Debug)]
+pub struct Descriptor {
+    kind: Kind,
+    key: Key,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Pkh,
+    Wpkh,
+    Tr,
+}
+
+#[derive(Debug)]
+enum Key {
+    Single(PrivateKey),
+    Extended {
+        xprv: ExtendedPrivKey,
+        path: DerivationPath,
+        /// Whether the final `*` step is hardened, if there is one
+        wildcard: Option<bool>,
+    },
+}
+
+impl FromStr for Descriptor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let descriptor = match s.split_once('#') {
+            Some((descriptor, sum)) => {
+                let expected = checksum(descriptor)?;
+                ensure!(
+                    sum == expected,
+                    "invalid checksum `{}` (expected `{}`)",
+                    sum,
+                    expected
+                );
+                descriptor
+            }
+            None => s,
+        };
+
+        let (name, inner) = split_function(descriptor)?;
+        let kind = match name {
+            "pkh" => Kind::Pkh,
+            "tr" if inner.contains(',') => {
+                bail!("`tr()` descriptors with a script tree are not supported")
+            }
+            "tr" => Kind::Tr,
+            "multi" | "sortedmulti" => bail!("multisig descriptors are not supported yet"),
+            "sh" | "wsh" if inner.starts_with("multi(") || inner.starts_with("sortedmulti(") => {
+                bail!("multisig descriptors are not supported yet")
+            }
+            "wpkh" => Kind::Wpkh,
+            "sh" if inner.starts_with("wpkh(") => {
+                bail!("P2SH-wrapped P2WPKH descriptors cannot be signed yet")
+            }
+            _ => bail!("unsupported descriptor `{}()`", name),
+        };
+
+        Ok(Self {
+            kind,
+            key: parse_key(inner)?,
+        })
+    }
+}
+
+// The xprv is parsed again for every use, so each copy is wiped once it is done with
+impl Drop for Descriptor {
+    fn drop(&mut self) {
+        match &mut self.key {
+            Key::Single(private_key) => private_key.inner.non_secure_erase(),
+            Key::Extended { xprv, .. } => xprv.private_key.non_secure_erase(),
+        }
+    }
+}
+
+impl Descriptor {
+    /// Derives the private key and the scriptPubKey at `index`, which replaces the `*` step.
+    /// Descriptors without a `*` step ignore it.
+    pub fn derive(&self, index: u32) -> Result<(PrivateKey, ScriptBuf), anyhow::Error> {
+        let secp = Secp256k1::new();
+
+        let private_key = match &self.key {
+            Key::Single(private_key) => *private_key,
+            Key::Extended {
+                xprv,
+                path,
+                wildcard,
+            } => {
+                let path = match wildcard {
+                    Some(false) => path.child(ChildNumber::from_normal_idx(index)?),
+                    Some(true) => path.child(ChildNumber::from_hardened_idx(index)?),
+                    None => path.clone(),
+                };
+                let mut derived = xprv.derive_priv(&secp, &path)?;
+                let private_key = derived.to_priv();
+                derived.private_key.non_secure_erase();
+                private_key
+            }
+        };
+        let public_key = private_key.public_key(&secp);
+
+        let script_pubkey = match self.kind {
+            Kind::Pkh => ScriptBuf::new_p2pkh(&public_key.pubkey_hash()),
+            Kind::Wpkh => ScriptBuf::new_v0_p2wpkh(
+                &public_key
+                    .wpubkey_hash()
+                    .ok_or_else(|| anyhow!("`wpkh()` descriptors need a compressed key"))?,
+            ),
+            Kind::Tr => {
+                ensure!(
+                    public_key.compressed,
+                    "`tr()` descriptors need a compressed key"
+                );
+                let (internal_key, _) = public_key.inner.x_only_public_key();
+                ScriptBuf::new_v1_p2tr(&secp, internal_key, None)
+            }
+        };
+
+        Ok((private_key, script_pubkey))
+    }
+}
+
+/// Splits `name(inner)` into its parts
+fn split_function(s: &str) -> Result<(&str, &str), anyhow::Error> {
+    s.strip_suffix(')')
+        .and_then(|s| s.split_once('('))
+        .ok_or_else(|| anyhow!("malformed descriptor `{}`", s))
+}
+
+/// Parses `[fingerprint/origin/path]KEY/path/*`, where the key origin is informational only
+fn parse_key(s: &str) -> Result<Key, anyhow::Error> {
+    let s = match s.strip_prefix('[') {
+        Some(rest) => {
+            let (origin, rest) = rest
+                .split_once(']')
+                .ok_or_else(|| anyhow!("unterminated key origin in `{}`", s))?;
+            let (fingerprint, path) = origin.split_once('/').unwrap_or((origin, ""));
+            ensure!(
+                fingerprint.len() == 8 && fingerprint.chars().all(|c| c.is_ascii_hexdigit()),
+                "key origin fingerprint `{}` must be 8 hexadecimals",
+                fingerprint
+            );
+            if !path.is_empty() {
+                DerivationPath::from_str(&format!("m/{}", path))
+                    .with_context(|| format!("invalid key origin path `{}`", path))?;
+            }
+            rest
+        }
+        None => s,
+    };
+
+    let mut steps = s.split('/');
+    let key = steps.next().unwrap_or_default();
+    if ["xpub", "tpub"]
+        .iter()
+        .any(|prefix| key.starts_with(prefix))
+    {
+        bail!(
+            "`{}...` is a public key, signing needs an xprv or tprv",
+            &key[..4]
+        );
+    }
+    if !["xprv", "tprv"]
+        .iter()
+        .any(|prefix| key.starts_with(prefix))
+    {
+        let private_key =
+            PrivateKey::from_wif(key).context("key must be an xprv, a tprv or a WIF")?;
+        ensure!(
+            steps.next().is_none(),
+            "derivation steps need an xprv or tprv"
+        );
+        return Ok(Key::Single(private_key));
+    }
+
+    let xprv = ExtendedPrivKey::from_str(key).context("invalid extended private key")?;
+    let mut path = Vec::new();
+    let mut wildcard = None;
+    for step in steps {
+        ensure!(wildcard.is_none(), "`*` must be the last derivation step");
+        match step {
+            "*" => wildcard = Some(false),
+            "*'" | "*h" => wildcard = Some(true),
+            _ => path.push(
+                ChildNumber::from_str(step)
+                    .with_context(|| format!("invalid derivation step `{}`", step))?,
+            ),
+        }
+    }
+
+    Ok(Key::Extended {
+        xprv,
+        path: DerivationPath::from(path),
+        wildcard,
+    })
+}
+
+/// Computes the BIP380 descriptor checksum
+fn checksum(descriptor: &str) -> Result<String, anyhow::Error> {
+    fn polymod(c: u64, value: u64) -> u64 {
+        const GENERATOR: [u64; 5] = [
+            0xf5dee51989,
+            0xa9fdca3312,
+            0x1bab10e32d,
+            0x3706b1677a,
+            0x644d626ffd,
+        ];
+        let c0 = c >> 35;
+        let mut c = ((c & 0x7ffffffff) << 5) ^ value;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (c0 >> i) & 1 == 1 {
+                c ^= generator;
+            }
+        }
+        c
+    }
+
+    let mut c = 1;
+    let mut class = 0;
+    let mut class_count = 0;
+    for ch in descriptor.chars() {
+        let position = INPUT_CHARSET
+            .find(ch)
+            .ok_or_else(|| anyhow!("invalid character `{}` in descriptor", ch))?
+            as u64;
+        c = polymod(c, position & 31);
+        class = class * 3 + (position >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = polymod(c, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        c = polymod(c, class);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+
+    Ok((0..8)
+        .map(|i| CHECKSUM_CHARSET[((c >> (5 * (7 - i))) & 31) as usize] as char)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Address;
+    use rstest::*;
+
+    use super::*;
+
+    // Root key of the "abandon ... about" mnemonic
+    const XPRV: &str = "xprv9s21ZrQH143K3GJpoapnV8SFfukcVBSfeCficPSGfubmSFDxo1kuHnLisriDvSnRRuL2Qrg5ggqHKNVpxR86QEC8w35uxmGoggxtQTPvfUu";
+
+    #[rstest]
+    #[case("raw(deadbeef)", "89f8spxm")]
+    #[case(
+        "pkh(cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP)",
+        "eqkrm2fv"
+    )]
+    fn test_checksum(#[case] descriptor: &str, #[case] expected: &str) {
+        assert_eq!(checksum(descriptor).unwrap(), expected)
+    }
+
+    #[rstest]
+    // BIP86 test vector: m/86'/0'/0'/0/0
+    #[case(
+        format!("tr({}/86'/0'/0'/0/*)#zeg4ksen", XPRV),
+        0,
+        "KyRv5iFPHG7iB5E4CqvMzH3WFJVhbfYK4VY7XAedd9Ys69mEsPLQ",
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+    )]
+    // BIP44: m/44'/0'/0'/0/0
+    #[case(
+        format!("pkh([73c5da0a/44'/0'/0']{}/44'/0'/0'/0/*)#fvnfv88h", XPRV),
+        0,
+        "L4p2b9VAf8k5aUahF1JCJUzZkgNEAqLfq8DDdQiyAprQAKSbu8hf",
+        "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA"
+    )]
+    // BIP84: m/84'/0'/0'/0/0
+    #[case(
+        format!("wpkh([73c5da0a/84'/0'/0']{}/84'/0'/0'/0/*)#sxg5k9ax", XPRV),
+        0,
+        "KyZpNDKnfs94vbrwhJneDi77V6jF64PWPF8x5cdJb8ifgg2DUc9d",
+        "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+    )]
+    #[case(
+        "pkh(cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP)".to_string(),
+        7,
+        "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP",
+        "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx"
+    )]
+    fn test_derive(
+        #[case] descriptor: String,
+        #[case] index: u32,
+        #[case] expected_key: &str,
+        #[case] expected_address: &str,
+    ) {
+        let (private_key, script_pubkey) = Descriptor::from_str(&descriptor)
+            .unwrap()
+            .derive(index)
+            .unwrap();
+        assert_eq!(private_key.to_wif(), expected_key);
+        assert_eq!(
+            Address::from_script(&script_pubkey, private_key.network)
+                .unwrap()
+                .to_string(),
+            expected_address
+        );
+    }
+
+    #[rstest]
+    #[case(
+        format!("tr({}/86'/0'/0'/0/*)#zeg4ksem", XPRV),
+        "invalid checksum `zeg4ksem` (expected `zeg4ksen`)"
+    )]
+    #[case(
+        format!("multi(1,{}/0/*)", XPRV),
+        "multisig descriptors are not supported yet"
+    )]
+    #[case(
+        format!("wsh(sortedmulti(1,{}/0/*))", XPRV),
+        "multisig descriptors are not supported yet"
+    )]
+    #[case(
+        format!("sh(wpkh({}/49'/0'/0'/0/*))", XPRV),
+        "P2SH-wrapped P2WPKH descriptors cannot be signed yet"
+    )]
+    #[case(
+        "pkh(xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8/0/*)".to_string(),
+        "`xpub...` is a public key, signing needs an xprv or tprv"
+    )]
+    #[case(
+        format!("pkh({}/*/0)", XPRV),
+        "`*` must be the last derivation step"
+    )]
+    #[case(format!("combo({})", XPRV), "unsupported descriptor `combo()`")]
+    fn test_from_str_invalid(#[case] descriptor: String, #[case] expected: &str) {
+        assert_eq!(
+            Descriptor::from_str(&descriptor).unwrap_err().to_string(),
+            expected
+        )
+    }
+}
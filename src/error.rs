@@ -0,0 +1,99 @@
+use std::fmt;
+
+/// Failures callers may want to tell apart, e.g. to point at the offending input
+#[derive(Debug)]
+pub enum TxError {
+    /// A private key does not parse in its format (WIF or hex)
+    InvalidPrivateKey {
+        name: String,
+        format: &'static str,
+        reason: String,
+    },
+    /// The txid of an input is not 32 bytes of hex
+    InvalidTxid { input_index: usize, txid: String },
+    /// The UTXOs don't cover the outputs and the fee
+    InsufficientFunds { needed: u64, available: u64 },
+    /// An output would be below the dust threshold of its scriptPubKey
+    DustOutput {
+        output: String,
+        amount: u64,
+        threshold: u64,
+    },
+    /// The sighash of an input could not be computed from the scriptPubKey it spends
+    SighashComputation { input_index: usize, reason: String },
+    /// An input could not be signed, or its signature does not verify
+    SigningFailed { input_index: usize, reason: String },
+    /// A builder method was called before the transaction was created
+    BuilderStateError { method: &'static str },
+    /// Any other failure, with the context it happened in
+    Other(anyhow::Error),
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxError::InvalidPrivateKey {
+                name,
+                format,
+                reason,
+            } => write!(
+                f,
+                "{} is not a valid {} private key: {}",
+                name, format, reason
+            ),
+            TxError::InvalidTxid { input_index, txid } => {
+                write!(f, "invalid txid `{}` for input {}", txid, input_index)
+            }
+            TxError::InsufficientFunds { needed, available } => write!(
+                f,
+                "insufficient funds: {} satoshi needed, {} satoshi available ({} satoshi short)",
+                needed,
+                available,
+                needed - available
+            ),
+            TxError::DustOutput {
+                output,
+                amount,
+                threshold,
+            } => write!(
+                f,
+                "{} of {} satoshi is below the dust threshold of {} satoshi",
+                output, amount, threshold
+            ),
+            TxError::SighashComputation {
+                input_index,
+                reason,
+            } => write!(
+                f,
+                "cannot compute the sighash of input {}: {}",
+                input_index, reason
+            ),
+            TxError::SigningFailed {
+                input_index,
+                reason,
+            } => write!(f, "cannot sign input {}: {}", input_index, reason),
+            TxError::BuilderStateError { method } => write!(
+                f,
+                "`{}` needs the transaction from `create_without_sig` first",
+                method
+            ),
+            TxError::Other(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for TxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TxError::Other(error) => error.source(),
+            _ => None,
+        }
+    }
+}
+
+/// Recovers a `TxError` raised inside code that otherwise uses anyhow
+impl From<anyhow::Error> for TxError {
+    fn from(error: anyhow::Error) -> Self {
+        error.downcast().unwrap_or_else(TxError::Other)
+    }
+}
@@ -0,0 +1,217 @@
+use anyhow::anyhow;
+
+use crate::{
+    args::Prevout,
+    http::{self, Failure, RetryPolicy},
+    json::Value,
+};
+
+/// Minimal Esplora REST API client
+pub struct EsploraClient {
+    url: String,
+    retry_policy: RetryPolicy,
+}
+
+impl EsploraClient {
+    pub fn new(url: &str, retry_policy: RetryPolicy) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            retry_policy,
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<Value, anyhow::Error> {
+        let url = format!("{}{}", self.url, path);
+
+        self.retry_policy.run(|timeout| {
+            let response =
+                http::get(&url, timeout).map_err(|e| e.context(format!("GET {} failed", path)))?;
+            if !(200..300).contains(&response.status) {
+                let error = anyhow!(
+                    "GET {} failed with HTTP status {}: {}",
+                    path,
+                    response.status,
+                    response.body.trim()
+                );
+                return Err(match response.status {
+                    500.. => Failure::Transient(error),
+                    _ => Failure::Permanent(error),
+                });
+            }
+            Value::parse(&response.body).map_err(|e| {
+                Failure::Permanent(e.context(format!("GET {} returned no JSON", path)))
+            })
+        })
+    }
+
+    /// Lists the UTXOs of `address`. Esplora leaves out their scriptPubKey, which is the
+    /// address's own `script_pubkey` for all of them
+    pub fn address_utxos(
+        &self,
+        address: &str,
+        script_pubkey: &str,
+    ) -> Result<Vec<Prevout>, anyhow::Error> {
+        let path = format!("/address/{}/utxo", address);
+        let utxos = self.get(&path)?;
+        let utxos = utxos
+            .as_array()
+            .ok_or_else(|| anyhow!("GET {} returned no array", path))?;
+
+        utxos
+            .iter()
+            .map(|utxo| {
+                let invalid =
+                    |key: &str| anyhow!("GET {} returned a UTXO without a valid `{}`", path, key);
+                Ok(Prevout {
+                    txid: utxo
+                        .get("txid")
+                        .and_then(Value::as_str)
+                        .ok_or_else(|| invalid("txid"))?
+                        .to_string(),
+                    vout: utxo
+                        .get("vout")
+                        .and_then(Value::as_u64)
+                        .and_then(|vout| u32::try_from(vout).ok())
+                        .ok_or_else(|| invalid("vout"))?,
+                    amount: utxo
+                        .get("value")
+                        .and_then(Value::as_u64)
+                        .ok_or_else(|| invalid("value"))?,
+                    script_pubkey: script_pubkey.to_string(),
+                    sighash: None,
+                    private_key: None,
+                })
+            })
+            .collect()
+    }
+
+    /// Looks up output `vout` of transaction `txid`, for the amount and scriptPubKey of a UTXO
+    /// given by its outpoint only
+    pub fn prevout(&self, txid: &str, vout: u32) -> Result<Prevout, anyhow::Error> {
+        let path = format!("/tx/{}", txid);
+        let tx = self.get(&path)?;
+        let output = tx
+            .get("vout")
+            .and_then(Value::as_array)
+            .ok_or_else(|| anyhow!("GET {} returned no `vout` array", path))?
+            .get(vout as usize)
+            .ok_or_else(|| anyhow!("transaction {} has no output {}", txid, vout))?;
+        let invalid =
+            |key: &str| anyhow!("GET {} returned an output without a valid `{}`", path, key);
+
+        Ok(Prevout {
+            txid: txid.to_string(),
+            vout,
+            amount: output
+                .get("value")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| invalid("value"))?,
+            script_pubkey: output
+                .get("scriptpubkey")
+                .and_then(Value::as_str)
+                .ok_or_else(|| invalid("scriptpubkey"))?
+                .to_string(),
+            sighash: None,
+            private_key: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bitcoin::secp256k1::All;
+    use rstest::*;
+
+    use super::*;
+    use crate::{args::Args, tx::TxBuilder};
+
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy {
+            retries: 0,
+            timeout: Duration::from_secs(5),
+            base_delay: Duration::from_millis(1),
+        }
+    }
+
+    #[rstest]
+    fn test_address_utxos() {
+        let (url, handle) = http::serve_once(
+            200,
+            r#"[{"txid":"d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331","vout":1,"status":{"confirmed":true,"block_height":2430000},"value":4847873}]"#,
+        );
+        let utxos = EsploraClient::new(&format!("{}/testnet/api/", url), retry_policy())
+            .address_utxos(
+                "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx",
+                "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac",
+            )
+            .unwrap();
+        let request = handle.join().unwrap();
+
+        assert_eq!(
+            utxos,
+            vec![Prevout {
+                txid: "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331"
+                    .to_string(),
+                vout: 1,
+                amount: 4847873,
+                script_pubkey: "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string(),
+                sighash: None,
+                private_key: None,
+            }]
+        );
+        assert!(request.starts_with(
+            "GET /testnet/api/address/mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx/utxo HTTP/1.1"
+        ));
+    }
+
+    #[rstest]
+    fn test_prevout() {
+        let (url, handle) = http::serve_once(
+            200,
+            r#"{"txid":"d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331","version":1,"locktime":0,"vout":[{"scriptpubkey":"76a914a997f6d478624028ea1f36082e7ceb5d79d7567188ac","scriptpubkey_type":"p2pkh","value":100},{"scriptpubkey":"76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac","scriptpubkey_address":"mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx","scriptpubkey_type":"p2pkh","value":4847873}]}"#,
+        );
+        let prevout = EsploraClient::new(&url, retry_policy())
+            .prevout(
+                "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331",
+                1,
+            )
+            .unwrap();
+        let request = handle.join().unwrap();
+        assert!(request.starts_with(
+            "GET /tx/d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331 HTTP/1.1"
+        ));
+
+        let args = Args {
+            source_address: "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string(),
+            destination_address: Some("mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string()),
+            private_key: Some("cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP".into()),
+            send_amount: Some(100),
+            ..Default::default()
+        };
+        let tx_builder = TxBuilder::<All>::new_with_prevouts(&args, &[prevout]).unwrap();
+        assert_eq!(tx_builder.utxo_amount(), 4847873);
+    }
+
+    #[rstest]
+    #[case(
+        400,
+        "Invalid Bitcoin address",
+        "GET /address/x/utxo failed with HTTP status 400: Invalid Bitcoin address"
+    )]
+    #[case(
+        200,
+        r#"[{"txid":"d73e","vout":1}]"#,
+        "GET /address/x/utxo returned a UTXO without a valid `value`"
+    )]
+    fn test_address_utxos_error(#[case] status: u16, #[case] body: &str, #[case] expected: &str) {
+        let (url, handle) = http::serve_once(status, body);
+        let error = EsploraClient::new(&url, retry_policy())
+            .address_utxos("x", "")
+            .unwrap_err();
+        handle.join().unwrap();
+
+        assert_eq!(error.to_string(), expected)
+    }
+}
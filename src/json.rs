@@ -0,0 +1,329 @@
+use std::fmt;
+
+use anyhow::{bail, ensure};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    // Kept as the raw literal so that satoshi amounts never go through a float
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn parse(input: &str) -> Result<Value, anyhow::Error> {
+        let mut parser = Parser {
+            chars: input.chars().collect(),
+            pos: 0,
+        };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        ensure!(
+            parser.pos == parser.chars.len(),
+            "unexpected trailing characters at position {}",
+            parser.pos
+        );
+        Ok(value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Removes the field `key` of an object, returning its value
+    pub fn take(&mut self, key: &str) -> Option<Value> {
+        match self {
+            Value::Object(entries) => {
+                let i = entries.iter().position(|(k, _)| k == key)?;
+                Some(entries.remove(i).1)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Value>> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write_string(f, s),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn parse_value(&mut self) -> Result<Value, anyhow::Error> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.parse_object(),
+            Some('[') => self.parse_array(),
+            Some('"') => Ok(Value::String(self.parse_string()?)),
+            Some('t') => self.parse_literal("true", Value::Bool(true)),
+            Some('f') => self.parse_literal("false", Value::Bool(false)),
+            Some('n') => self.parse_literal("null", Value::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.parse_number(),
+            Some(c) => bail!("unexpected character `{}` at position {}", c, self.pos),
+            None => bail!("unexpected end of input"),
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Value, anyhow::Error> {
+        self.expect('{')?;
+        let mut entries = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Value::Object(entries));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            entries.push((key, value));
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::Object(entries)),
+                _ => bail!("expected `,` or `}}` at position {}", self.pos - 1),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, anyhow::Error> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Value::Array(items));
+        }
+
+        loop {
+            items.push(self.parse_value()?);
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::Array(items)),
+                _ => bail!("expected `,` or `]` at position {}", self.pos - 1),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, anyhow::Error> {
+        self.expect('"')?;
+        let mut result = String::new();
+
+        loop {
+            match self.next() {
+                Some('"') => return Ok(result),
+                Some('\\') => match self.next() {
+                    Some('"') => result.push('"'),
+                    Some('\\') => result.push('\\'),
+                    Some('/') => result.push('/'),
+                    Some('b') => result.push('\u{08}'),
+                    Some('f') => result.push('\u{0c}'),
+                    Some('n') => result.push('\n'),
+                    Some('r') => result.push('\r'),
+                    Some('t') => result.push('\t'),
+                    Some('u') => {
+                        let code: String = (0..4).filter_map(|_| self.next()).collect();
+                        let code = u32::from_str_radix(&code, 16)?;
+                        match char::from_u32(code) {
+                            Some(c) => result.push(c),
+                            None => bail!("invalid unicode escape at position {}", self.pos),
+                        }
+                    }
+                    _ => bail!("invalid escape sequence at position {}", self.pos - 1),
+                },
+                Some(c) => result.push(c),
+                None => bail!("unterminated string"),
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Value, anyhow::Error> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() || "+-.eE".contains(c) {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        let literal: String = self.chars[start..self.pos].iter().collect();
+        ensure!(
+            literal.parse::<f64>().is_ok(),
+            "invalid number `{}` at position {}",
+            literal,
+            start
+        );
+        Ok(Value::Number(literal))
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Value) -> Result<Value, anyhow::Error> {
+        for expected in literal.chars() {
+            if self.next() != Some(expected) {
+                bail!("invalid literal at position {}", self.pos - 1);
+            }
+        }
+        Ok(value)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), anyhow::Error> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => bail!(
+                "expected `{}` at position {}",
+                expected,
+                self.pos.saturating_sub(1)
+            ),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case("null", Value::Null)]
+    #[case("true", Value::Bool(true))]
+    #[case("4847873", Value::Number("4847873".to_string()))]
+    #[case(r#""a\"bあ""#, Value::String("a\"bあ".to_string()))]
+    #[case(
+        r#" [ {"vout": 1}, [] ] "#,
+        Value::Array(vec![
+            Value::Object(vec![("vout".to_string(), Value::Number("1".to_string()))]),
+            Value::Array(vec![]),
+        ])
+    )]
+    fn test_parse(#[case] input: &str, #[case] expected: Value) {
+        assert_eq!(Value::parse(input).unwrap(), expected)
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("[1,]")]
+    #[case(r#"{"a" 1}"#)]
+    #[case(r#""unterminated"#)]
+    #[case("[1] 2")]
+    fn test_parse_invalid(#[case] input: &str) {
+        assert!(Value::parse(input).is_err())
+    }
+
+    #[rstest]
+    #[case(r#"{ "method" : "getblockchaininfo", "params" : [1, true, null] }"#)]
+    #[case(r#"["tab\t", "quote\"", "\u0001"]"#)]
+    fn test_display_roundtrip(#[case] input: &str) {
+        let value = Value::parse(input).unwrap();
+        assert_eq!(Value::parse(&value.to_string()).unwrap(), value)
+    }
+
+    #[rstest]
+    #[case(r#"{"amount": 4847873}"#, Some(4847873))]
+    #[case(r#"{"amount": -1}"#, None)]
+    #[case(r#"{"amount": 1.5}"#, None)]
+    #[case(r#"{"amount": "1"}"#, None)]
+    fn test_as_u64(#[case] input: &str, #[case] expected: Option<u64>) {
+        let value = Value::parse(input).unwrap();
+        assert_eq!(value.get("amount").and_then(Value::as_u64), expected)
+    }
+}
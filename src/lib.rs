@@ -0,0 +1,25 @@
+//! Builds and signs Bitcoin transactions from UTXOs given by hand. The command-line tool is a
+//! thin wrapper over [`TxBuilder`], which [`TxParams`] configures without going through clap.
+
+pub mod args;
+pub mod base64;
+pub mod batch;
+pub mod broadcast;
+mod descriptor;
+pub mod error;
+pub mod fetch;
+mod http;
+mod json;
+pub mod log;
+pub mod qr;
+pub mod rpc;
+pub mod secret;
+pub mod select;
+pub mod serialize;
+pub mod tx;
+
+pub use crate::{
+    args::Prevout,
+    error::TxError,
+    tx::{Tx, TxBuilder, TxParams},
+};
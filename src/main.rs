@@ -1,21 +1,341 @@
-use bitcoin::secp256k1::All;
-use clap::Parser;
-
-mod args;
-mod tx;
+use std::{
+    env, fs,
+    io::{self, IsTerminal},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use crate::{args::Args, tx::TxBuilder};
+use anyhow::{bail, ensure, Context};
+use bitcoin::{secp256k1::All, Network, Txid};
+use clap::Parser;
+#[cfg(feature = "liquid")]
+use generate_btc_transaction::serialize;
+use generate_btc_transaction::{
+    args::{self, Args, Cli, Command, OutputFormat},
+    base64, batch, broadcast,
+    fetch::EsploraClient,
+    log::{self, warning, Level},
+    rpc::{MempoolAcceptance, RpcClient},
+    select,
+    tx::{self, FeeEstimate, Tx, TxBuilder},
+};
 
 fn main() -> Result<(), anyhow::Error> {
-    let args = Args::parse();
+    let cli = Cli::parse_from(args::with_config(env::args().collect())?);
+    let mut args = match (cli.command, cli.args) {
+        (Some(command), _) => return run(command),
+        (None, Some(args)) => args,
+        (None, None) => unreachable!("clap requires the build arguments without a subcommand"),
+    };
+    log::init(args.log_level());
+    args.load_private_key()?;
     args.validate()?;
+    fetch_utxo(&mut args)?;
+
+    let mut tx_builder = match args.testnet_faucet_spend {
+        true => faucet_tx_builder(&args)?,
+        false => TxBuilder::<All>::new(&args)?,
+    };
+
+    if let Some(fee_rates) = &args.fee_table {
+        print_fee_table(&tx_builder.fee_table(fee_rates));
+        return Ok(());
+    }
+    if args.estimate {
+        println!("{}", tx_builder.estimated_vsize());
+        return Ok(());
+    }
+
+    let prev_txs = args
+        .prev_tx
+        .iter()
+        .map(|hex| tx::decode_transaction(hex))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut lookup_prev_tx = |txid: &Txid| {
+        if let Some(prev_tx) = prev_txs.iter().find(|prev_tx| prev_tx.txid() == *txid) {
+            return Ok(Some(prev_tx.clone()));
+        }
+        match &args.rpc_url {
+            Some(url) => {
+                args.ensure_online("previous transactions")?;
+                RpcClient::new(url, args.retry_policy())
+                    .raw_transaction(txid)
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    };
+    tx_builder.check_prev_txs(&mut lookup_prev_tx)?;
+
+    let network = args
+        .network()
+        .map_or_else(|| tx_builder.key_network(), Network::from);
+    tx_builder.create_without_sig()?;
+    if args.psbt {
+        let psbt = tx_builder.psbt(&mut lookup_prev_tx)?;
+        args.emit(&base64::encode(&psbt.serialize()))?;
+        return Ok(());
+    }
+    if args.explain {
+        let selection = match args.testnet_faucet_spend {
+            true => "selected largest first from the UTXOs of the source address",
+            false => "given on the command line",
+        };
+        eprintln!("{}", tx_builder.explain(selection)?);
+    }
+    if args.dump_unsigned {
+        let tx = tx_builder.build()?;
+        if args.annotate {
+            eprintln!("{}", tx.annotated(network));
+        }
+        #[cfg(feature = "liquid")]
+        if args.liquid {
+            let serializer = serialize::LiquidSerializer::new(tx_builder.fee()?);
+            args.emit(&tx.output_with(&serializer))?;
+            return Ok(());
+        }
+        print_tx(&args, &tx, network)?;
+        return Ok(());
+    }
+    let tx = tx_builder.sign()?.verify()?.build()?;
+
+    // Test networks accept dust, which the README sample relies on
+    let dust_output_error = tx.dust_output_error(network);
+    if network != Network::Bitcoin {
+        if let Some(error) = &dust_output_error {
+            warning!("{}", error);
+        }
+    }
+    for error in [
+        tx.standardness_error(),
+        tx.spendable_output_error(),
+        dust_output_error.filter(|_| network == Network::Bitcoin),
+        tx_builder.relay_fee_error(),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if !args.force {
+            bail!("{} (use `--force` to build it anyway)", error);
+        }
+        warning!("{}", error);
+    }
+
+    // Checked before a broadcast as well, which is when a locktimed transaction would be lost
+    if args.broadcast || log::enabled(Level::Info) {
+        let (tip_height, tip_time) = chain_tip(&args)?;
+        if let Some(warning) = tx.lock_time_warning(tip_height, tip_time) {
+            warning!("{}", warning);
+        }
+    }
+
+    let summary = format!(
+        "{}\ntxid: {}",
+        tx_builder.summary(&args.destination())?,
+        tx.txid()
+    );
+    args.confirm_output(
+        &summary,
+        network == Network::Bitcoin,
+        &mut io::stdin().lock(),
+        io::stdin().is_terminal(),
+    )?;
+
+    if let Some(path) = &args.export_verification {
+        fs::write(path, tx_builder.verification_bundle()?.to_string()).with_context(|| {
+            format!("failed to write `--export-verification` {}", path.display())
+        })?;
+    }
+    if args.annotate {
+        eprintln!("{}", tx.annotated(network));
+    }
+    if args.show_txid {
+        eprintln!("txid: {}", tx.txid());
+    }
+
+    if args.dry_run {
+        println!(
+            "{}",
+            tx.summary(tx_builder.utxo_amount(), tx_builder.change())
+        );
+        return Ok(());
+    }
+    if args.test_mempool_accept {
+        args.ensure_online("`--test-mempool-accept`")?;
+        let url = args.rpc_url.as_deref().unwrap_or_default();
+        let acceptance = RpcClient::new(url, args.retry_policy()).test_mempool_accept(&tx.hex())?;
+        match acceptance {
+            MempoolAcceptance::Accepted { fee, vsize } => println!(
+                "accepted: {} satoshi for {} vbytes ({:.2} sat/vB)",
+                fee,
+                vsize,
+                fee as f64 / vsize as f64
+            ),
+            MempoolAcceptance::Rejected { reason } => {
+                bail!("the node would reject the transaction: {}", reason)
+            }
+        }
+        return Ok(());
+    }
+    if args.broadcast {
+        args.ensure_online("the broadcast")?;
+        let url = args.broadcast_url.as_deref().unwrap_or_default();
+        println!(
+            "{}",
+            broadcast::broadcast(url, &tx.hex(), args.retry_policy())?
+        );
+        return Ok(());
+    }
+    if args.testnet_faucet_spend {
+        let url = args.esplora_url.as_deref().unwrap_or_default();
+        println!(
+            "{}",
+            broadcast::broadcast(url, &tx.hex(), args.retry_policy())?
+        );
+        return Ok(());
+    }
+
+    print_tx(&args, &tx, network)
+}
+
+/// Returns the height and median time past of the chain tip from `--rpc-url`, or no height and
+/// the system time when there is no node to ask. The tip is only advisory, so a failed lookup
+/// falls back to the system time
+fn chain_tip(args: &Args) -> Result<(Option<u32>, u32), anyhow::Error> {
+    if let Some(url) = &args.rpc_url {
+        let tip = args
+            .ensure_online("the chain tip")
+            .and_then(|()| RpcClient::new(url, args.retry_policy()).chain_tip());
+        match tip {
+            Ok(tip) => return Ok((Some(tip.height), tip.median_time)),
+            Err(e) => warning!(
+                "failed to get the chain tip, checking the locktime against the system time: {:#}",
+                e
+            ),
+        }
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    Ok((None, u32::try_from(now)?))
+}
 
-    let tx = TxBuilder::<All>::new(&args)?
-        .create_without_sig()?
-        .sign()?
-        .build();
+fn print_tx(args: &Args, tx: &Tx, network: Network) -> Result<(), anyhow::Error> {
+    match args.output_format {
+        OutputFormat::Hex => args.emit(&tx.output()),
+        OutputFormat::Json => args.emit(&tx.output_json(network).to_string()),
+    }
+}
 
-    println!("{}", tx.output());
+/// Fills in the amount and scriptPubKey of `--utxo-txid` and `--utxo-tx-index` from
+/// `--esplora-url` when they are omitted
+fn fetch_utxo(args: &mut Args) -> Result<(), anyhow::Error> {
+    let (Some(url), Some(txid), Some(vout)) =
+        (&args.esplora_url, &args.utxo_txid, args.utxo_tx_index)
+    else {
+        return Ok(());
+    };
+    let descriptor_script_pubkey = args.descriptor.is_some();
+    if args.utxo_amount.is_some() && (args.utxo_script_pubkey.is_some() || descriptor_script_pubkey)
+    {
+        return Ok(());
+    }
 
+    args.ensure_online("the UTXO")?;
+    let prevout = EsploraClient::new(url, args.retry_policy()).prevout(txid, vout)?;
+    args.utxo_amount.get_or_insert(prevout.amount);
+    if !descriptor_script_pubkey {
+        args.utxo_script_pubkey.get_or_insert(prevout.script_pubkey);
+    }
     Ok(())
 }
+
+/// Spends the fewest, largest UTXOs of the source address that cover the payment
+fn faucet_tx_builder(args: &Args) -> Result<TxBuilder<All>, anyhow::Error> {
+    // Never touch real coins
+    let script_pubkey = tx::address_script_pubkey(&args.source_address, Network::Testnet)
+        .context("`--testnet-faucet-spend` only spends from testnet addresses")?;
+    tx::address_script_pubkey(&args.destination(), Network::Testnet)
+        .context("`--testnet-faucet-spend` only sends to testnet addresses")?;
+
+    args.ensure_online("the faucet UTXOs")?;
+    let url = args.esplora_url.as_deref().unwrap_or_default();
+    let pool = EsploraClient::new(url, args.retry_policy())
+        .address_utxos(&args.source_address, &script_pubkey.to_hex_string())?;
+    let selection = select::largest_first(pool, args.max_inputs, |selection| {
+        Ok(TxBuilder::<All>::new_with_prevouts(args, selection)?.funded_change())
+    })?;
+    log::info!(
+        "selected {} UTXOs, leaving {} satoshi of change",
+        selection.prevouts.len(),
+        selection.change
+    );
+
+    let tx_builder = TxBuilder::<All>::new_with_prevouts(args, &selection.prevouts)?;
+    tx_builder
+        .ensure_key_network(Network::Testnet)
+        .context("`--testnet-faucet-spend` only signs with testnet keys")?;
+    Ok(tx_builder)
+}
+
+fn run(command: Command) -> Result<(), anyhow::Error> {
+    match command {
+        Command::ScriptPubkey { address, network } => {
+            let script_pubkey = tx::address_script_pubkey(&address, network.into())?;
+            println!("{}", script_pubkey.to_hex_string());
+        }
+        Command::Decode { raw, network } => {
+            println!("{}", Tx::decode(&raw)?.output_json(network.into()));
+        }
+        Command::Batch => {
+            let input = io::read_to_string(io::stdin()).context("failed to read the batch")?;
+            println!("{}", batch::build(&input)?);
+        }
+        Command::Verify { raw, utxo } => {
+            Tx::decode(&raw)?.verify_signatures(&utxo)?;
+            println!("verified the signatures of {} inputs", utxo.len());
+        }
+        Command::DeriveAddress {
+            private_key,
+            private_key_format,
+            network,
+            uncompressed,
+            source_address,
+        } => {
+            let address = tx::derive_address(
+                &private_key,
+                private_key_format,
+                network.map(Network::from),
+                uncompressed,
+            )?
+            .to_string();
+            if let Some(source_address) = source_address {
+                ensure!(
+                    address == source_address,
+                    "the private key controls {}, not `--source-address` {}",
+                    address,
+                    source_address
+                );
+            }
+            println!("{}", address);
+        }
+    }
+    Ok(())
+}
+
+fn print_fee_table(estimates: &[FeeEstimate]) {
+    println!(
+        "{:>14} {:>12} {:>14}",
+        "rate (sat/vB)", "fee (sat)", "change (sat)"
+    );
+    for estimate in estimates {
+        let change = match estimate.change {
+            Some(change) => change.to_string(),
+            None => "insufficient".to_string(),
+        };
+        println!(
+            "{:>14} {:>12} {:>14}{}",
+            estimate.fee_rate,
+            estimate.fee,
+            change,
+            if estimate.dust { "  dust" } else { "" }
+        );
+    }
+}
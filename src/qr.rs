@@ -0,0 +1,656 @@
+//! QR codes (ISO/IEC 18004) in byte mode at error correction level L, the largest capacity,
+//! to move a transaction or PSBT to an air-gapped signer
+
+use anyhow::bail;
+
+/// Error correction codewords per block at level L, by version
+const ECC_CODEWORDS_PER_BLOCK: [usize; 41] = [
+    0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28, 30,
+    30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+];
+/// Error correction blocks at level L, by version
+const ECC_BLOCKS: [usize; 41] = [
+    0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13, 14,
+    15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25,
+];
+const MAX_VERSION: usize = 40;
+const LEVEL_L: u32 = 0b01; // format bits of error correction level L
+const QUIET_ZONE: usize = 4; // modules of light border scanners need
+
+pub struct QrCode {
+    size: usize,
+    /// Dark modules, row by row
+    modules: Vec<bool>,
+    /// Modules of the finder, timing and alignment patterns and the format and version
+    /// information, which the data and the mask leave alone
+    function: Vec<bool>,
+}
+
+/// Encodes `data` in the smallest version it fits
+pub fn encode(data: &[u8]) -> Result<QrCode, anyhow::Error> {
+    let Some(version) = (1..=MAX_VERSION).find(|&version| data.len() <= byte_capacity(version))
+    else {
+        bail!(
+            "{} bytes don't fit in a QR code, which holds at most {} bytes",
+            data.len(),
+            byte_capacity(MAX_VERSION)
+        );
+    };
+
+    let mut qr = QrCode::new(version);
+    qr.draw_codewords(&add_ecc_and_interleave(
+        version,
+        &data_codewords(version, data),
+    ));
+    // Masks are their own inverse, so each one can be tried and undone
+    let mask = (0..8)
+        .min_by_key(|&mask| {
+            qr.apply_mask(mask);
+            qr.draw_format_bits(mask);
+            let penalty = qr.penalty();
+            qr.apply_mask(mask);
+            penalty
+        })
+        .unwrap();
+    qr.apply_mask(mask);
+    qr.draw_format_bits(mask);
+
+    Ok(qr)
+}
+
+impl QrCode {
+    /// An empty symbol with its function patterns drawn
+    fn new(version: usize) -> Self {
+        let size = version * 4 + 17;
+        let mut qr = Self {
+            size,
+            modules: vec![false; size * size],
+            function: vec![false; size * size],
+        };
+
+        // Timing patterns
+        for i in 0..size {
+            qr.set_function(6, i, i % 2 == 0);
+            qr.set_function(i, 6, i % 2 == 0);
+        }
+        // Finder patterns with their separators
+        for (x, y) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            for dy in -4..=4_isize {
+                for dx in -4..=4_isize {
+                    let (Some(x), Some(y)) = (x.checked_add_signed(dx), y.checked_add_signed(dy))
+                    else {
+                        continue;
+                    };
+                    let distance = dx.abs().max(dy.abs());
+                    if x < size && y < size {
+                        qr.set_function(x, y, distance != 2 && distance != 4);
+                    }
+                }
+            }
+        }
+        // Alignment patterns, except where they would overlap the finder patterns
+        let positions = alignment_positions(version);
+        let last = positions.len().saturating_sub(1);
+        for (i, &x) in positions.iter().enumerate() {
+            for (j, &y) in positions.iter().enumerate() {
+                if (i, j) == (0, 0) || (i, j) == (0, last) || (i, j) == (last, 0) {
+                    continue;
+                }
+                for dy in -2..=2_isize {
+                    for dx in -2..=2_isize {
+                        qr.set_function(
+                            x.wrapping_add_signed(dx),
+                            y.wrapping_add_signed(dy),
+                            dx.abs().max(dy.abs()) != 1,
+                        );
+                    }
+                }
+            }
+        }
+        // Reserved until the mask is chosen
+        qr.draw_format_bits(0);
+        qr.draw_version(version);
+
+        qr
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y * self.size + x] = dark;
+        self.function[y * self.size + x] = true;
+    }
+
+    fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.size + x]
+    }
+
+    /// Like `is_dark`, in coordinates that include the quiet zone
+    fn is_dark_padded(&self, x: usize, y: usize) -> bool {
+        let inside = |n: usize| (QUIET_ZONE..QUIET_ZONE + self.size).contains(&n);
+        inside(x) && inside(y) && self.is_dark(x - QUIET_ZONE, y - QUIET_ZONE)
+    }
+
+    fn draw_format_bits(&mut self, mask: u8) {
+        let bits = format_bits(mask);
+        let bit = |i: usize| (bits >> i) & 1 == 1;
+        let size = self.size;
+
+        // Around the top left finder pattern
+        for i in 0..=5 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+        // Split between the other two, next to the always dark module
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        self.set_function(8, size - 8, true);
+    }
+
+    fn draw_version(&mut self, version: usize) {
+        if version < 7 {
+            return;
+        }
+        let bits = version_bits(version);
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 == 1;
+            let (a, b) = (self.size - 11 + i % 3, i / 3);
+            self.set_function(a, b, dark);
+            self.set_function(b, a, dark);
+        }
+    }
+
+    /// Fills the non-function modules in the zigzag of two-module columns, from the bottom right
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let size = self.size;
+        let mut i = 0;
+        let mut right = size - 1;
+
+        loop {
+            // The vertical timing pattern is skipped as a whole
+            if right == 6 {
+                right = 5;
+            }
+            for vertical in 0..size {
+                for x in [right, right - 1] {
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward {
+                        size - 1 - vertical
+                    } else {
+                        vertical
+                    };
+                    if !self.function[y * size + x] && i < codewords.len() * 8 {
+                        self.modules[y * size + x] = (codewords[i / 8] >> (7 - i % 8)) & 1 == 1;
+                        i += 1;
+                    }
+                }
+            }
+            if right < 2 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: u8) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let i = y * self.size + x;
+                self.modules[i] ^= invert && !self.function[i];
+            }
+        }
+    }
+
+    /// Scores how hard the symbol is to scan, for picking the mask: long runs, 2x2 blocks,
+    /// look-alikes of the finder pattern and an imbalance of dark and light modules
+    fn penalty(&self) -> usize {
+        const FINDER_LIKE: [bool; 11] = [
+            true, false, true, true, true, false, true, false, false, false, false,
+        ];
+        let size = self.size;
+        let mut score = 0;
+
+        for line in 0..size {
+            let row: Vec<bool> = (0..size).map(|i| self.is_dark(i, line)).collect();
+            let column: Vec<bool> = (0..size).map(|i| self.is_dark(line, i)).collect();
+            for modules in [row, column] {
+                let mut run = 1;
+                for pair in modules.windows(2) {
+                    if pair[0] == pair[1] {
+                        run += 1;
+                        score += match run {
+                            5 => 3,
+                            6.. => 1,
+                            _ => 0,
+                        };
+                    } else {
+                        run = 1;
+                    }
+                }
+                for window in modules.windows(FINDER_LIKE.len()) {
+                    if window == FINDER_LIKE || window.iter().eq(FINDER_LIKE.iter().rev()) {
+                        score += 40;
+                    }
+                }
+            }
+        }
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let color = self.is_dark(x, y);
+                if [(x + 1, y), (x, y + 1), (x + 1, y + 1)]
+                    .iter()
+                    .all(|&(x, y)| self.is_dark(x, y) == color)
+                {
+                    score += 3;
+                }
+            }
+        }
+        let total = size * size;
+        let dark = self.modules.iter().filter(|&&dark| dark).count();
+        let deviation = (dark * 20).abs_diff(total * 10).div_ceil(total);
+        score + deviation.saturating_sub(1) * 10
+    }
+
+    /// Number of modules on a side, without the quiet zone
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Draws the symbol with half blocks, two rows of modules per line. Light modules are the
+    /// blocks, so that it scans on the usual light-on-dark terminal
+    pub fn to_terminal(&self) -> String {
+        let width = self.size + 2 * QUIET_ZONE;
+        let mut result = String::new();
+
+        for y in (0..width).step_by(2) {
+            for x in 0..width {
+                let top = !self.is_dark_padded(x, y);
+                // Below the last row is the terminal background
+                let bottom = y + 1 < width && !self.is_dark_padded(x, y + 1);
+                result.push(match (top, bottom) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            result.push('\n');
+        }
+        result
+    }
+
+    /// Encodes the symbol as a grayscale PNG with `scale` pixels per module
+    pub fn to_png(&self, scale: usize) -> Vec<u8> {
+        let width = (self.size + 2 * QUIET_ZONE) * scale;
+        let mut pixels = Vec::with_capacity((width + 1) * width);
+        for y in 0..width {
+            // No filter
+            pixels.push(0);
+            for x in 0..width {
+                pixels.push(match self.is_dark_padded(x / scale, y / scale) {
+                    true => 0,
+                    false => 255,
+                });
+            }
+        }
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        // 8-bit grayscale, deflate, no interlace
+        header.extend_from_slice(&[8, 0, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        push_chunk(&mut png, b"IHDR", &header);
+        push_chunk(&mut png, b"IDAT", &zlib_stored(&pixels));
+        push_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+/// Most bytes `version` holds: the data codewords minus the mode indicator and the length
+fn byte_capacity(version: usize) -> usize {
+    (data_codeword_count(version) * 8 - 4 - char_count_bits(version)) / 8
+}
+
+fn char_count_bits(version: usize) -> usize {
+    match version {
+        1..=9 => 8,
+        _ => 16,
+    }
+}
+
+/// Modules left for data and error correction once the function patterns are drawn
+fn raw_data_modules(version: usize) -> usize {
+    let mut result = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let alignments = version / 7 + 2;
+        result -= (25 * alignments - 10) * alignments - 55;
+        if version >= 7 {
+            result -= 36;
+        }
+    }
+    result
+}
+
+fn data_codeword_count(version: usize) -> usize {
+    raw_data_modules(version) / 8 - ECC_CODEWORDS_PER_BLOCK[version] * ECC_BLOCKS[version]
+}
+
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return vec![];
+    }
+    let count = version / 7 + 2;
+    let step = match version {
+        32 => 26,
+        _ => (version * 4 + count * 2 + 1) / (count * 2 - 2) * 2,
+    };
+    let size = version * 4 + 17;
+    let mut result: Vec<usize> = (0..count - 1).map(|i| size - 7 - i * step).collect();
+    result.push(6);
+    result.reverse();
+    result
+}
+
+/// The byte mode segment of `data`, terminated and padded to the data capacity of `version`
+fn data_codewords(version: usize, data: &[u8]) -> Vec<u8> {
+    let capacity = data_codeword_count(version);
+    let mut bits = Vec::new();
+    push_bits(&mut bits, 0b0100, 4);
+    push_bits(&mut bits, data.len(), char_count_bits(version));
+    for &byte in data {
+        push_bits(&mut bits, byte.into(), 8);
+    }
+    let terminator = (capacity * 8 - bits.len()).min(4);
+    push_bits(&mut bits, 0, terminator);
+    let padding = (8 - bits.len() % 8) % 8;
+    push_bits(&mut bits, 0, padding);
+
+    let mut codewords: Vec<u8> = bits
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | u8::from(bit)))
+        .collect();
+    for pad in [0xec, 0x11].into_iter().cycle() {
+        if codewords.len() >= capacity {
+            break;
+        }
+        codewords.push(pad);
+    }
+    codewords
+}
+
+fn push_bits(bits: &mut Vec<bool>, value: usize, len: usize) {
+    bits.extend((0..len).rev().map(|i| (value >> i) & 1 == 1));
+}
+
+/// Splits the data codewords into blocks, appends the Reed-Solomon codewords of each and
+/// interleaves the blocks
+fn add_ecc_and_interleave(version: usize, data: &[u8]) -> Vec<u8> {
+    let block_count = ECC_BLOCKS[version];
+    let ecc_len = ECC_CODEWORDS_PER_BLOCK[version];
+    let raw_codewords = raw_data_modules(version) / 8;
+    let short_blocks = block_count - raw_codewords % block_count;
+    let short_len = raw_codewords / block_count;
+    let divisor = reed_solomon_divisor(ecc_len);
+
+    let mut data = data;
+    let blocks: Vec<Vec<u8>> = (0..block_count)
+        .map(|i| {
+            let (block, rest) = data.split_at(short_len - ecc_len + usize::from(i >= short_blocks));
+            data = rest;
+            let ecc = reed_solomon_remainder(block, &divisor);
+            let mut block = block.to_vec();
+            // Lines the error correction up with that of the long blocks
+            if i < short_blocks {
+                block.push(0);
+            }
+            block.extend(ecc);
+            block
+        })
+        .collect();
+
+    let mut result = Vec::with_capacity(raw_codewords);
+    for i in 0..=short_len {
+        for (j, block) in blocks.iter().enumerate() {
+            if i != short_len - ecc_len || j >= short_blocks {
+                result.push(block[i]);
+            }
+        }
+    }
+    result
+}
+
+/// Multiplies in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u16 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11d);
+        z ^= ((u16::from(y) >> i) & 1) * u16::from(x);
+    }
+    z as u8
+}
+
+/// Coefficients of the generator polynomial of `degree`, highest first without the leading 1
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0; degree];
+    result[degree - 1] = 1;
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    result
+}
+
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (coefficient, &d) in result.iter_mut().zip(divisor) {
+            *coefficient ^= gf_multiply(d, factor);
+        }
+    }
+    result
+}
+
+/// Level L and the mask, with their BCH code, masked so that they are never all light
+fn format_bits(mask: u8) -> u32 {
+    let data = LEVEL_L << 3 | u32::from(mask);
+    let mut remainder = data;
+    for _ in 0..10 {
+        remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+    }
+    (data << 10 | remainder) ^ 0x5412
+}
+
+fn version_bits(version: usize) -> u32 {
+    let mut remainder = version as u32;
+    for _ in 0..12 {
+        remainder = (remainder << 1) ^ ((remainder >> 11) * 0x1f25);
+    }
+    (version as u32) << 12 | remainder
+}
+
+fn push_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wraps `data` in a zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut result = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = data.chunks(u16::MAX as usize).collect();
+    for (i, block) in blocks.iter().enumerate() {
+        result.push(u8::from(i + 1 == blocks.len()));
+        let len = block.len() as u16;
+        result.extend_from_slice(&len.to_le_bytes());
+        result.extend_from_slice(&(!len).to_le_bytes());
+        result.extend_from_slice(block);
+    }
+    result.extend_from_slice(&adler32(data).to_be_bytes());
+    result
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case(1, 17)]
+    #[case(10, 271)]
+    #[case(40, 2953)]
+    fn test_byte_capacity(#[case] version: usize, #[case] expected: usize) {
+        assert_eq!(byte_capacity(version), expected)
+    }
+
+    #[rstest]
+    #[case(2, vec![6, 18])]
+    #[case(7, vec![6, 22, 38])]
+    #[case(32, vec![6, 34, 60, 86, 112, 138])]
+    #[case(36, vec![6, 24, 50, 76, 102, 128, 154])]
+    fn test_alignment_positions(#[case] version: usize, #[case] expected: Vec<usize>) {
+        assert_eq!(alignment_positions(version), expected)
+    }
+
+    #[rstest]
+    fn test_reed_solomon_remainder() {
+        // "HELLO WORLD" as 1-M in alphanumeric mode
+        let data = [
+            32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17,
+        ];
+        assert_eq!(
+            reed_solomon_remainder(&data, &reed_solomon_divisor(10)),
+            [196, 35, 39, 119, 235, 215, 231, 226, 93, 23]
+        )
+    }
+
+    #[rstest]
+    #[case(0, 0b111011111000100)]
+    #[case(1, 0b111001011110011)]
+    #[case(2, 0b111110110101010)]
+    #[case(3, 0b111100010011101)]
+    #[case(4, 0b110011000101111)]
+    #[case(5, 0b110001100011000)]
+    #[case(6, 0b110110001000001)]
+    #[case(7, 0b110100101110110)]
+    fn test_format_bits(#[case] mask: u8, #[case] expected: u32) {
+        assert_eq!(format_bits(mask), expected)
+    }
+
+    #[rstest]
+    #[case(7, 0b000111110010010100)]
+    #[case(40, 0b101000110001101001)]
+    fn test_version_bits(#[case] version: usize, #[case] expected: u32) {
+        assert_eq!(version_bits(version), expected)
+    }
+
+    #[rstest]
+    // The README sample transaction as printed
+    #[case(384, Some(69))]
+    #[case(17, Some(21))]
+    #[case(18, Some(25))]
+    #[case(2953, Some(177))]
+    #[case(2954, None)]
+    fn test_encode(#[case] len: usize, #[case] expected: Option<usize>) {
+        let qr = encode(&vec![b'0'; len]).ok();
+        assert_eq!(qr.as_ref().map(QrCode::size), expected);
+
+        if let Some(qr) = qr {
+            let size = qr.size();
+            // Finder pattern corners, timing patterns and the dark module
+            for (x, y) in [(0, 0), (size - 1, 0), (0, size - 1), (8, size - 8)] {
+                assert!(qr.is_dark(x, y));
+            }
+            for i in 8..size - 8 {
+                assert_eq!(qr.is_dark(i, 6), i % 2 == 0);
+                assert_eq!(qr.is_dark(6, i), i % 2 == 0);
+            }
+        }
+    }
+
+    #[rstest]
+    fn test_encode_too_large() {
+        assert_eq!(
+            encode(&[0; 3000]).err().unwrap().to_string(),
+            "3000 bytes don't fit in a QR code, which holds at most 2953 bytes"
+        )
+    }
+
+    #[rstest]
+    fn test_to_terminal() {
+        let terminal = encode(b"0x0100").unwrap().to_terminal();
+        let lines: Vec<&str> = terminal.lines().collect();
+
+        // 21 modules and the quiet zone, two rows per line
+        assert_eq!(lines.len(), 15);
+        assert!(lines.iter().all(|line| line.chars().count() == 29));
+        assert!(lines[0].chars().all(|c| c == '█'));
+    }
+
+    #[rstest]
+    fn test_to_png() {
+        let png = encode(b"0x0100").unwrap().to_png(2);
+
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR"));
+        // 29 modules of 2 pixels
+        assert_eq!(png[16..24], [0, 0, 0, 58, 0, 0, 0, 58]);
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+    }
+
+    #[rstest]
+    #[case(b"", 0x0000_0000)]
+    #[case(b"123456789", 0xcbf4_3926)]
+    fn test_crc32(#[case] data: &[u8], #[case] expected: u32) {
+        assert_eq!(crc32(data), expected)
+    }
+}
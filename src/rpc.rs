@@ -0,0 +1,272 @@
+use anyhow::anyhow;
+use bitcoin::{Transaction, Txid};
+
+use crate::{
+    args,
+    http::{self, Failure, RetryPolicy},
+    json::Value,
+    tx,
+};
+
+/// Minimal Bitcoin Core JSON-RPC client
+pub struct RpcClient {
+    url: String,
+    retry_policy: RetryPolicy,
+}
+
+/// Height and median time past of the current best block
+#[derive(Debug, PartialEq, Eq)]
+pub struct ChainTip {
+    pub height: u32,
+    pub median_time: u32,
+}
+
+/// The node's verdict on a transaction from `testmempoolaccept`
+#[derive(Debug, PartialEq, Eq)]
+pub enum MempoolAcceptance {
+    /// With the fee in satoshi and the size in vbytes the node computed
+    Accepted { fee: u64, vsize: u64 },
+    /// With the node's reason, e.g. `min relay fee not met`
+    Rejected { reason: String },
+}
+
+impl RpcClient {
+    pub fn new(url: &str, retry_policy: RetryPolicy) -> Self {
+        Self {
+            url: url.to_string(),
+            retry_policy,
+        }
+    }
+
+    pub fn call(&self, method: &str, params: Vec<Value>) -> Result<Value, anyhow::Error> {
+        let request = Value::Object(vec![
+            ("jsonrpc".to_string(), Value::String("1.0".to_string())),
+            (
+                "id".to_string(),
+                Value::String(env!("CARGO_PKG_NAME").to_string()),
+            ),
+            ("method".to_string(), Value::String(method.to_string())),
+            ("params".to_string(), Value::Array(params)),
+        ]);
+
+        self.retry_policy.run(|timeout| {
+            let response = http::post(&self.url, "application/json", &request.to_string(), timeout)
+                .map_err(|e| e.context(format!("RPC `{}` failed", method)))?;
+
+            // Bitcoin Core answers RPC errors with a 5xx status and an error object, which
+            // retrying won't fix, so look at the body before the status
+            let body = match Value::parse(&response.body) {
+                Ok(body) => body,
+                Err(_) => {
+                    let error = anyhow!(
+                        "RPC `{}` failed with HTTP status {}: {}",
+                        method,
+                        response.status,
+                        response.body.trim()
+                    );
+                    return Err(match response.status {
+                        500.. => Failure::Transient(error),
+                        _ => Failure::Permanent(error),
+                    });
+                }
+            };
+
+            match body.get("error") {
+                Some(Value::Null) | None => {}
+                Some(error) => {
+                    let message = error.get("message").and_then(Value::as_str).unwrap_or("");
+                    return Err(Failure::Permanent(anyhow!(
+                        "RPC `{}` returned an error: {}",
+                        method,
+                        message
+                    )));
+                }
+            }
+            body.get("result")
+                .cloned()
+                .ok_or_else(|| Failure::Permanent(anyhow!("RPC `{}` returned no result", method)))
+        })
+    }
+
+    pub fn chain_tip(&self) -> Result<ChainTip, anyhow::Error> {
+        let info = self.call("getblockchaininfo", vec![])?;
+        let field = |key: &str| {
+            info.get(key)
+                .and_then(Value::as_u64)
+                .and_then(|n| u32::try_from(n).ok())
+                .ok_or_else(|| anyhow!("`getblockchaininfo` returned no valid `{}`", key))
+        };
+
+        Ok(ChainTip {
+            height: field("blocks")?,
+            median_time: field("mediantime")?,
+        })
+    }
+
+    /// Asks whether the node would accept a raw transaction (hex) into its mempool, without
+    /// submitting it
+    pub fn test_mempool_accept(&self, raw_hex: &str) -> Result<MempoolAcceptance, anyhow::Error> {
+        let result = self.call(
+            "testmempoolaccept",
+            vec![Value::Array(vec![Value::String(raw_hex.to_string())])],
+        )?;
+        let entry = result
+            .as_array()
+            .and_then(|entries| entries.first())
+            .ok_or_else(|| anyhow!("`testmempoolaccept` returned no result for the transaction"))?;
+
+        match entry.get("allowed") {
+            Some(Value::Bool(true)) => {
+                let vsize = entry
+                    .get("vsize")
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| anyhow!("`testmempoolaccept` returned no valid `vsize`"))?;
+                // Amounts are decimal BTC
+                let fee = match entry.get("fees").and_then(|fees| fees.get("base")) {
+                    Some(Value::Number(btc)) => args::parse_amount(&format!("{}btc", btc))?,
+                    _ => return Err(anyhow!("`testmempoolaccept` returned no valid `fees`")),
+                };
+                Ok(MempoolAcceptance::Accepted { fee, vsize })
+            }
+            Some(Value::Bool(false)) => Ok(MempoolAcceptance::Rejected {
+                reason: entry
+                    .get("reject-reason")
+                    .and_then(Value::as_str)
+                    .unwrap_or("no reason given")
+                    .to_string(),
+            }),
+            _ => Err(anyhow!("`testmempoolaccept` returned no valid `allowed`")),
+        }
+    }
+
+    /// Needs `-txindex` on the node unless the transaction is in the mempool or the wallet
+    pub fn raw_transaction(&self, txid: &Txid) -> Result<Transaction, anyhow::Error> {
+        let hex = self.call("getrawtransaction", vec![Value::String(txid.to_string())])?;
+        let hex = hex
+            .as_str()
+            .ok_or_else(|| anyhow!("`getrawtransaction` returned no hex"))?;
+        tx::decode_transaction(hex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rstest::*;
+
+    use super::*;
+
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy {
+            retries: 2,
+            timeout: Duration::from_secs(5),
+            base_delay: Duration::from_millis(1),
+        }
+    }
+
+    #[rstest]
+    fn test_chain_tip() {
+        let (url, handle) = http::serve_once(
+            200,
+            r#"{"result":{"chain":"test","blocks":2430000,"mediantime":1687500000},"error":null,"id":"x"}"#,
+        );
+        let tip = RpcClient::new(&url, retry_policy()).chain_tip().unwrap();
+        let request = handle.join().unwrap();
+
+        assert_eq!(
+            tip,
+            ChainTip {
+                height: 2430000,
+                median_time: 1687500000
+            }
+        );
+        assert!(request.contains(r#""method":"getblockchaininfo""#));
+    }
+
+    #[rstest]
+    fn test_raw_transaction() {
+        // The sample transaction in the README
+        let hex = "01000000011798d99e33691fe595ac0fb00224adf249657b5a8d8cf7574928accce7c4d70e010000006a473044022053f663276bf1673a32f55d213428983d5fdfa7146ac3884439475ee90257c21b02206b1ecc97e8601a67eaf13067a70386737b0f7d59b7e44817d927db17829275b301210303998660a6a026b2f8aa72d37a077b6a76b282b2d5b73fc582fdc274f66fa5bcffffffff0264000000000000001976a914a997f6d478624028ea1f36082e7ceb5d79d7567188acd41d0000000000001976a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac00000000";
+        let (url, handle) = http::serve_once(
+            200,
+            &format!(r#"{{"result":"{}","error":null,"id":"x"}}"#, hex),
+        );
+        let txid = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef"
+            .parse()
+            .unwrap();
+        let tx = RpcClient::new(&url, retry_policy())
+            .raw_transaction(&txid)
+            .unwrap();
+        let request = handle.join().unwrap();
+
+        assert_eq!(tx.output.len(), 2);
+        assert!(request.contains(
+            r#""params":["0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef"]"#
+        ));
+    }
+
+    #[rstest]
+    #[case(
+        r#"[{"txid":"t","wtxid":"w","allowed":true,"vsize":226,"fees":{"base":0.00001000}}]"#,
+        MempoolAcceptance::Accepted { fee: 1000, vsize: 226 }
+    )]
+    #[case(
+        r#"[{"txid":"t","wtxid":"w","allowed":false,"reject-reason":"min relay fee not met"}]"#,
+        MempoolAcceptance::Rejected { reason: "min relay fee not met".to_string() }
+    )]
+    fn test_test_mempool_accept(#[case] result: &str, #[case] expected: MempoolAcceptance) {
+        let (url, handle) = http::serve_once(
+            200,
+            &format!(r#"{{"result":{},"error":null,"id":"x"}}"#, result),
+        );
+        let acceptance = RpcClient::new(&url, retry_policy())
+            .test_mempool_accept("0100")
+            .unwrap();
+        let request = handle.join().unwrap();
+
+        assert_eq!(acceptance, expected);
+        assert!(request.contains(r#""method":"testmempoolaccept","params":[["0100"]]"#));
+    }
+
+    #[rstest]
+    fn test_call_error() {
+        let (url, handle) = http::serve_once(
+            500,
+            r#"{"result":null,"error":{"code":-28,"message":"Loading block index..."},"id":"x"}"#,
+        );
+        let error = RpcClient::new(&url, retry_policy())
+            .chain_tip()
+            .unwrap_err();
+        // Error objects are permanent, so the single canned response must be enough
+        handle.join().unwrap();
+
+        assert_eq!(
+            error.to_string(),
+            "RPC `getblockchaininfo` returned an error: Loading block index..."
+        );
+    }
+
+    #[rstest]
+    #[case(vec![(503, "busy"), (200, r#"{"result":{"blocks":1,"mediantime":2},"error":null}"#)], true, 2)]
+    #[case(vec![(401, ""), (200, r#"{"result":{"blocks":1,"mediantime":2},"error":null}"#)], false, 1)]
+    fn test_call_retries(
+        #[case] responses: Vec<(u16, &str)>,
+        #[case] expected: bool,
+        #[case] expected_requests: usize,
+    ) {
+        let (url, handle) = http::serve(responses);
+        let result = RpcClient::new(&url, retry_policy()).chain_tip();
+        // Unblock the server if the client stopped before using every canned response
+        drop(std::net::TcpStream::connect(
+            url.trim_start_matches("http://"),
+        ));
+        let requests = handle.join().unwrap();
+
+        assert_eq!(result.is_ok(), expected);
+        assert_eq!(
+            requests.iter().filter(|r| !r.is_empty()).count(),
+            expected_requests
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Private key material that is wiped from memory once it goes out of scope.
+//!
+//! The wipe is best effort, as with the `zeroize` crate: it overwrites the value in place with a
+//! write the compiler may not elide, but `SecretKey` and `PrivateKey` are `Copy`, so copies the
+//! compiler made elsewhere (registers, moved-from stack slots) are out of reach, as are buffers
+//! a `String` left behind when it grew.
+
+use std::{fmt, hint, mem, ops::Deref};
+
+use bitcoin::{secp256k1::SecretKey, PrivateKey};
+
+/// Text holding a private key (a WIF, hex or a descriptor's xprv), zeroed when it is dropped
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct SecretString(String);
+
+impl SecretString {
+    fn erase(&mut self) {
+        // Zero the whole allocation, not just the text, without reallocating
+        let mut bytes = mem::take(&mut self.0).into_bytes();
+        bytes.clear();
+        bytes.resize(bytes.capacity(), 0);
+        hint::black_box(&bytes);
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl Deref for SecretString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+// Keeps keys out of `{:?}` output, e.g. of `Args`
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretString(..)")
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.erase();
+    }
+}
+
+/// The private keys a [`TxBuilder`](crate::TxBuilder) signs with, erased once it has signed
+/// and again when it is dropped
+pub struct SecretKeys(Vec<PrivateKey>);
+
+impl SecretKeys {
+    pub fn new(keys: Vec<PrivateKey>) -> Self {
+        Self(keys)
+    }
+
+    /// Overwrites every key with a fixed dummy one, keeping their networks
+    pub fn erase(&mut self) {
+        for key in &mut self.0 {
+            key.inner.non_secure_erase();
+        }
+    }
+}
+
+impl Deref for SecretKeys {
+    type Target = [PrivateKey];
+
+    fn deref(&self) -> &[PrivateKey] {
+        &self.0
+    }
+}
+
+impl Drop for SecretKeys {
+    fn drop(&mut self) {
+        self.erase();
+    }
+}
+
+/// A copy of a secret key taken for signing, erased when it goes out of scope, early returns
+/// included
+pub struct ScopedSecretKey(SecretKey);
+
+impl ScopedSecretKey {
+    pub fn new(key: SecretKey) -> Self {
+        Self(key)
+    }
+
+    fn erase(&mut self) {
+        self.0.non_secure_erase();
+    }
+}
+
+impl Deref for ScopedSecretKey {
+    type Target = SecretKey;
+
+    fn deref(&self) -> &SecretKey {
+        &self.0
+    }
+}
+
+impl Drop for ScopedSecretKey {
+    fn drop(&mut self) {
+        self.erase();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Network;
+    use rstest::*;
+
+    use super::*;
+    use crate::args::Args;
+
+    #[rstest]
+    fn test_erase() {
+        let key = PrivateKey::new(
+            SecretKey::from_slice(&[0x23; 32]).unwrap(),
+            Network::Testnet,
+        );
+        let mut keys = SecretKeys::new(vec![key, key]);
+        keys.erase();
+        assert!(keys.iter().all(|erased| erased.inner != key.inner));
+
+        let mut scoped = ScopedSecretKey::new(key.inner);
+        scoped.erase();
+        assert_ne!(*scoped, key.inner);
+
+        let mut text = SecretString::from("cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP");
+        assert_eq!(format!("{:?}", text), "SecretString(..)");
+        text.erase();
+        assert_eq!(&*text, "");
+    }
+
+    #[rstest]
+    fn test_input_key() {
+        let args = Args {
+            prevouts: Some(
+                r#"[{"txid": "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331", "vout": 1, "amount": 4847873, "script_pubkey": "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac", "private_key": "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP"}]"#.into(),
+            ),
+            ..Default::default()
+        };
+        let mut prevouts = args.prevouts().unwrap();
+        assert!(!format!("{:?}", prevouts)
+            .contains("cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP"));
+
+        let key = prevouts[0].private_key.as_mut().unwrap();
+        assert_eq!(
+            &**key,
+            "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP"
+        );
+        key.erase();
+        assert_eq!(&**key, "");
+    }
+}
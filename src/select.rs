@@ -0,0 +1,145 @@
+use std::cmp::Reverse;
+
+use anyhow::bail;
+
+use crate::args::Prevout;
+
+/// UTXOs picked from a pool, with what they leave over for the change
+#[derive(Debug, PartialEq, Eq)]
+pub struct Selection {
+    pub prevouts: Vec<Prevout>,
+    pub change: u64,
+}
+
+/// Adds UTXOs from the largest down until `covers` accepts the selection, returning the change
+/// it leaves. `covers` is given the whole selection so that it can charge the marginal fee of
+/// every added input. At most `max_inputs` UTXOs are added, so that a pool of many tiny ones
+/// fails instead of paying for an oversized transaction
+pub fn largest_first(
+    mut pool: Vec<Prevout>,
+    max_inputs: Option<usize>,
+    mut covers: impl FnMut(&[Prevout]) -> Result<Option<u64>, anyhow::Error>,
+) -> Result<Selection, anyhow::Error> {
+    pool.sort_by_key(|prevout| Reverse(prevout.amount));
+    let limit = max_inputs.map_or(pool.len(), |max| max.min(pool.len()));
+
+    for n in 1..=limit {
+        if let Some(change) = covers(&pool[..n])? {
+            pool.truncate(n);
+            return Ok(Selection {
+                prevouts: pool,
+                change,
+            });
+        }
+    }
+    if limit < pool.len() {
+        bail!(
+            "the payment and the fee can't be covered within `--max-inputs` {}: the {} largest \
+             of {} UTXOs total {} satoshi",
+            limit,
+            limit,
+            pool.len(),
+            pool[..limit]
+                .iter()
+                .map(|prevout| prevout.amount)
+                .sum::<u64>()
+        )
+    }
+    bail!(
+        "insufficient funds: {} UTXOs totaling {} satoshi can't cover the payment and the fee",
+        pool.len(),
+        pool.iter().map(|prevout| prevout.amount).sum::<u64>()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    fn prevout(amount: u64) -> Prevout {
+        Prevout {
+            txid: "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331".to_string(),
+            vout: 0,
+            amount,
+            script_pubkey: "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string(),
+            sighash: None,
+            private_key: None,
+        }
+    }
+
+    #[rstest]
+    // Target of 5_000 satoshi plus 100 satoshi per input
+    #[case(vec![1_000, 6_000, 3_000], Some((vec![6_000], 900)))]
+    #[case(vec![1_000, 4_000, 3_000], Some((vec![4_000, 3_000], 1_800)))]
+    // Exact matches leave no change
+    #[case(vec![5_100, 2_000], Some((vec![5_100], 0)))]
+    #[case(vec![3_000, 2_200], Some((vec![3_000, 2_200], 0)))]
+    // 4_000 + 1_000 alone would cover 5_000, but not the fee of the second input
+    #[case(vec![1_000, 4_000], None)]
+    #[case(vec![], None)]
+    fn test_largest_first(#[case] pool: Vec<u64>, #[case] expected: Option<(Vec<u64>, u64)>) {
+        let pool = pool.into_iter().map(prevout).collect();
+        let result = largest_first(pool, None, |selection| {
+            let total: u64 = selection.iter().map(|prevout| prevout.amount).sum();
+            Ok(total.checked_sub(5_000 + 100 * selection.len() as u64))
+        });
+
+        assert_eq!(
+            result.ok().map(|selection| (
+                selection
+                    .prevouts
+                    .iter()
+                    .map(|prevout| prevout.amount)
+                    .collect(),
+                selection.change
+            )),
+            expected
+        )
+    }
+
+    #[rstest]
+    fn test_largest_first_insufficient() {
+        let pool = vec![prevout(1_000), prevout(4_000)];
+        let error = largest_first(pool, None, |_| Ok(None)).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "insufficient funds: 2 UTXOs totaling 5000 satoshi can't cover the payment and the fee"
+        )
+    }
+
+    #[rstest]
+    // Target of 5_000 satoshi plus 100 satoshi per input
+    #[case(Some(3), Ok(vec![2_000, 2_000, 2_000]))]
+    #[case(
+        Some(2),
+        Err(
+            "the payment and the fee can't be covered within `--max-inputs` 2: the 2 largest of 4 \
+             UTXOs total 4000 satoshi"
+        )
+    )]
+    // A cap above the pool size is the same as none
+    #[case(
+        Some(10),
+        Ok(vec![2_000, 2_000, 2_000])
+    )]
+    fn test_largest_first_max_inputs(
+        #[case] max_inputs: Option<usize>,
+        #[case] expected: Result<Vec<u64>, &str>,
+    ) {
+        let pool = vec![prevout(2_000); 4];
+        let result = largest_first(pool, max_inputs, |selection| {
+            let total: u64 = selection.iter().map(|prevout| prevout.amount).sum();
+            Ok(total.checked_sub(5_000 + 100 * selection.len() as u64))
+        });
+
+        assert_eq!(
+            result
+                .map(|selection| selection.prevouts.iter().map(|p| p.amount).collect())
+                .map_err(|e| e.to_string()),
+            expected.map_err(str::to_string)
+        )
+    }
+}
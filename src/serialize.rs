@@ -0,0 +1,144 @@
+use bitcoin::{consensus::encode::serialize, Transaction};
+#[cfg(feature = "liquid")]
+use bitcoin::{hashes::hex::FromHex, VarInt};
+
+/// Turns a built transaction into the bytes of a particular wire format
+pub trait TxSerializer {
+    fn serialize(&self, tx: &Transaction) -> Vec<u8>;
+}
+
+/// Bitcoin consensus serialization, what every node expects
+pub struct BitcoinSerializer;
+
+impl TxSerializer for BitcoinSerializer {
+    fn serialize(&self, tx: &Transaction) -> Vec<u8> {
+        serialize(tx)
+    }
+}
+
+/// L-BTC, the policy asset of the Liquid network
+#[cfg(feature = "liquid")]
+const LIQUID_BITCOIN_ASSET: &str =
+    "6f0279e9ed041c3d710a9f57d0c02928416460c4b722ae3457a11eec381c526d";
+
+/// Elements/Liquid serialization with explicit (non-confidential) assets and values.
+/// Liquid pays its fee with an explicit output, which is appended last.
+#[cfg(feature = "liquid")]
+pub struct LiquidSerializer {
+    /// Asset ID in serialization (reversed) byte order
+    asset: [u8; 32],
+    fee: u64,
+}
+
+#[cfg(feature = "liquid")]
+impl LiquidSerializer {
+    pub fn new(fee: u64) -> Self {
+        let mut asset = <[u8; 32]>::from_hex(LIQUID_BITCOIN_ASSET).unwrap();
+        asset.reverse();
+        Self { asset, fee }
+    }
+
+    fn push_output(&self, bytes: &mut Vec<u8>, value: u64, script_pubkey: &[u8]) {
+        bytes.push(0x01); // explicit asset
+        bytes.extend_from_slice(&self.asset);
+        bytes.push(0x01); // explicit value, big-endian unlike Bitcoin
+        bytes.extend_from_slice(&value.to_be_bytes());
+        bytes.push(0x00); // no nonce
+        push_bytes(bytes, script_pubkey);
+    }
+}
+
+#[cfg(feature = "liquid")]
+impl TxSerializer for LiquidSerializer {
+    fn serialize(&self, tx: &Transaction) -> Vec<u8> {
+        let has_witness = tx.input.iter().any(|input| !input.witness.is_empty());
+        let mut bytes = serialize(&tx.version);
+        bytes.push(has_witness as u8);
+
+        bytes.extend(serialize(&VarInt(tx.input.len() as u64)));
+        for input in &tx.input {
+            bytes.extend(serialize(&input.previous_output));
+            push_bytes(&mut bytes, input.script_sig.as_bytes());
+            bytes.extend(serialize(&input.sequence));
+        }
+
+        bytes.extend(serialize(&VarInt(tx.output.len() as u64 + 1)));
+        for output in &tx.output {
+            self.push_output(&mut bytes, output.value, output.script_pubkey.as_bytes());
+        }
+        self.push_output(&mut bytes, self.fee, &[]);
+
+        bytes.extend(serialize(&tx.lock_time));
+
+        if has_witness {
+            for input in &tx.input {
+                bytes.extend([0x00, 0x00]); // no issuance amount or inflation keys rangeproof
+                bytes.extend(serialize(&input.witness));
+                bytes.push(0x00); // no peg-in witness
+            }
+            for _ in 0..=tx.output.len() {
+                bytes.extend([0x00, 0x00]); // no surjection proof or rangeproof
+            }
+        }
+
+        bytes
+    }
+}
+
+#[cfg(feature = "liquid")]
+fn push_bytes(bytes: &mut Vec<u8>, data: &[u8]) {
+    bytes.extend(serialize(&VarInt(data.len() as u64)));
+    bytes.extend_from_slice(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::tx;
+
+    // The sample transaction in the README
+    const SAMPLE_TX: &str = "01000000011798d99e33691fe595ac0fb00224adf249657b5a8d8cf7574928accce7c4d70e010000006a473044022053f663276bf1673a32f55d213428983d5fdfa7146ac3884439475ee90257c21b02206b1ecc97e8601a67eaf13067a70386737b0f7d59b7e44817d927db17829275b301210303998660a6a026b2f8aa72d37a077b6a76b282b2d5b73fc582fdc274f66fa5bcffffffff0264000000000000001976a914a997f6d478624028ea1f36082e7ceb5d79d7567188acd41d0000000000001976a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac00000000";
+
+    #[rstest]
+    fn test_bitcoin_serializer() {
+        let tx = tx::decode_transaction(SAMPLE_TX).unwrap();
+        assert_eq!(hex::encode(BitcoinSerializer.serialize(&tx)), SAMPLE_TX)
+    }
+
+    #[cfg(feature = "liquid")]
+    #[rstest]
+    fn test_liquid_serializer() {
+        let mut tx = tx::decode_transaction(SAMPLE_TX).unwrap();
+        tx.input[0].script_sig = bitcoin::ScriptBuf::new();
+        let asset = "016d521c38ec1ea15734ae22b7c46064412829c0d0579f0a713d1c04ede979026f";
+
+        assert_eq!(
+            hex::encode(LiquidSerializer::new(1000).serialize(&tx)),
+            [
+                "01000000",
+                "00", // no witness
+                "01",
+                "1798d99e33691fe595ac0fb00224adf249657b5a8d8cf7574928accce7c4d70e01000000",
+                "00",
+                "ffffffff",
+                "03", // destination, change and fee
+                asset,
+                "010000000000000064",
+                "00",
+                "1976a914a997f6d478624028ea1f36082e7ceb5d79d7567188ac",
+                asset,
+                "010000000000001dd4",
+                "00",
+                "1976a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac",
+                asset,
+                "0100000000000003e8",
+                "00",
+                "00",
+                "00000000",
+            ]
+            .concat()
+        )
+    }
+}
@@ -1,262 +1,5432 @@
-use std::{mem::size_of_val, str::FromStr};
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    str::FromStr,
+};
 
+use anyhow::{anyhow, bail, ensure, Context as _};
 use bitcoin::{
     absolute::LockTime,
     address::Address,
     blockdata::{
-        script::ScriptBuf,
+        script::{Instruction, PushBytesBuf, Script, ScriptBuf},
         transaction::{Sequence, Transaction, TxIn, TxOut},
         witness::Witness,
     },
-    consensus::encode::serialize,
-    hashes::{hex::FromHex, Hash},
-    secp256k1::{self, ecdsa::Signature, Context, Secp256k1, SecretKey, Signing},
-    sighash::SighashCache,
-    OutPoint,
-    PrivateKey,
-    PublicKey,
-    Txid,
+    consensus::encode::{deserialize, serialize},
+    ecdsa,
+    hashes::hex::FromHex,
+    key::TapTweak,
+    psbt::Psbt,
+    secp256k1::{self, ecdsa::Signature, Context, KeyPair, Secp256k1, Signing, Verification},
+    sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType},
+    taproot, Network, OutPoint, PrivateKey, PublicKey, Txid, VarInt,
 };
 
-use crate::args::Args;
+use crate::{
+    args::{Args, ChangeMatches, DustPolicy, KeyFormat, OutputOrder, Prevout},
+    error::TxError,
+    json::Value,
+    log::{debug, info, trace, warning},
+    secret::{ScopedSecretKey, SecretKeys, SecretString},
+    serialize::{BitcoinSerializer, TxSerializer},
+};
 
-const SIGHASH_ALL: u8 = 0x01;
-const INPUT_INDEX: usize = 0;
+const MAX_STANDARD_TX_WEIGHT: usize = 400_000; // Bitcoin Core relay policy
+const MIN_RELAY_FEE_RATE: u64 = 1; // satoshi/vbyte, Bitcoin Core relay policy
 const FEE: u64 = 1000; // sathoshi
+const MAX_FEE_PERCENT: u64 = 10; // of the input value, unless `--max-fee` is given
 
 pub struct Tx(Transaction);
 
+fn varint_len(n: usize) -> usize {
+    match n {
+        0..=0xfc => 1,
+        0xfd..=0xffff => 3,
+        0x10000..=0xffff_ffff => 5,
+        _ => 9,
+    }
+}
+
 impl Tx {
+    /// Decodes a raw transaction in hex, with or without the `0x` prefix `output` adds
+    pub fn decode(hex: &str) -> Result<Self, anyhow::Error> {
+        Ok(Tx(decode_transaction(hex.trim_start_matches("0x"))?))
+    }
+
     pub fn output(&self) -> String {
-        let raw: Vec<u8> = serialize(&self.0);
-        format!("0x{}", hex::encode(raw))
+        self.output_with(&BitcoinSerializer)
+    }
+
+    /// Like `output`, in the wire format of `serializer`
+    pub fn output_with(&self, serializer: &dyn TxSerializer) -> String {
+        format!("0x{}", hex::encode(serializer.serialize(&self.0)))
+    }
+
+    pub fn hex(&self) -> String {
+        hex::encode(serialize(&self.0))
+    }
+
+    /// Checks the signature of every input of a transaction signed elsewhere against the
+    /// outputs it spends, given in input order. The sighash type of each input is the one its
+    /// signature commits to, and the scripts of P2SH and P2WSH inputs are those pushed last
+    pub fn verify_signatures(&self, prevouts: &[Prevout]) -> Result<(), TxError> {
+        let transaction = &self.0;
+        if prevouts.len() != transaction.input.len() {
+            return Err(anyhow!(
+                "the transaction has {} inputs, but {} prevouts are given",
+                transaction.input.len(),
+                prevouts.len()
+            )
+            .into());
+        }
+        let utxos = transaction
+            .input
+            .iter()
+            .zip(prevouts)
+            .enumerate()
+            .map(|(input_index, (input, prevout))| Utxo::spent_by(input_index, input, prevout))
+            .collect::<Result<Vec<_>, TxError>>()?;
+        let spent = utxos
+            .iter()
+            .map(|utxo| TxOut {
+                value: utxo.amount,
+                script_pubkey: utxo.script_pubkey.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        let secp = Secp256k1::new();
+        let mut cache = SighashCache::new(transaction);
+        for (input_index, (utxo, input)) in utxos.iter().zip(&transaction.input).enumerate() {
+            TxBuilder::verify_input(&secp, &mut cache, input_index, utxo, input, &spent)?;
+        }
+        Ok(())
+    }
+
+    /// Double-SHA256 of the transaction serialized without witnesses, so signing doesn't change it
+    /// for SegWit inputs
+    pub fn txid(&self) -> Txid {
+        self.0.txid()
+    }
+
+    /// Decodes the transaction into JSON for other tools, with the raw hex alongside. Output
+    /// addresses are given for `network`, or `null` for scripts without one. Scripts come with
+    /// their disassembly (`*_asm`) for reading
+    pub fn output_json(&self, network: Network) -> Value {
+        let string = |s: String| Value::String(s);
+        let number = |n: u64| Value::Number(n.to_string());
+
+        let inputs = self
+            .0
+            .input
+            .iter()
+            .map(|input| {
+                Value::Object(vec![
+                    (
+                        "txid".to_string(),
+                        string(input.previous_output.txid.to_string()),
+                    ),
+                    (
+                        "vout".to_string(),
+                        number(input.previous_output.vout.into()),
+                    ),
+                    (
+                        "script_sig".to_string(),
+                        string(input.script_sig.to_hex_string()),
+                    ),
+                    (
+                        "script_sig_asm".to_string(),
+                        string(input.script_sig.to_asm_string()),
+                    ),
+                    ("sequence".to_string(), number(input.sequence.0.into())),
+                ])
+            })
+            .collect();
+        let outputs = self
+            .0
+            .output
+            .iter()
+            .map(|output| {
+                Value::Object(vec![
+                    ("value".to_string(), number(output.value)),
+                    (
+                        "script_pubkey".to_string(),
+                        string(output.script_pubkey.to_hex_string()),
+                    ),
+                    (
+                        "script_pubkey_asm".to_string(),
+                        string(output.script_pubkey.to_asm_string()),
+                    ),
+                    (
+                        "address".to_string(),
+                        script_address(&output.script_pubkey, network)
+                            .map_or(Value::Null, |address| string(address.to_string())),
+                    ),
+                ])
+            })
+            .collect();
+
+        Value::Object(vec![
+            ("txid".to_string(), string(self.0.txid().to_string())),
+            (
+                "version".to_string(),
+                Value::Number(self.0.version.to_string()),
+            ),
+            (
+                "locktime".to_string(),
+                number(self.0.lock_time.to_consensus_u32().into()),
+            ),
+            ("inputs".to_string(), Value::Array(inputs)),
+            ("outputs".to_string(), Value::Array(outputs)),
+            ("hex".to_string(), string(self.hex())),
+        ])
+    }
+
+    /// Returns why relay policy would reject the transaction as non-standard, if it would
+    pub fn standardness_error(&self) -> Option<String> {
+        let weight = self.0.weight().to_wu() as usize;
+        (weight > MAX_STANDARD_TX_WEIGHT).then(|| {
+            format!(
+                "transaction is non-standard: weight {} exceeds the policy limit of {} \
+                 ({} inputs, {} outputs)",
+                weight,
+                MAX_STANDARD_TX_WEIGHT,
+                self.0.input.len(),
+                self.0.output.len()
+            )
+        })
+    }
+
+    /// Returns an error unless some output can be spent, e.g. when all outputs are OP_RETURN
+    pub fn spendable_output_error(&self) -> Option<String> {
+        let spendable = self
+            .0
+            .output
+            .iter()
+            .any(|output| !output.script_pubkey.is_op_return() && output.value > 0);
+        (!spendable).then(|| "transaction has no spendable outputs".to_string())
+    }
+
+    /// Returns why relay policy would reject an output as dust, if it would. The threshold
+    /// depends on the size of the script, e.g. 546 satoshi for P2PKH and 294 for P2WPKH.
+    pub fn dust_output_error(&self, network: Network) -> Option<String> {
+        self.0
+            .output
+            .iter()
+            .enumerate()
+            .find(|(_, output)| {
+                !output.script_pubkey.is_op_return()
+                    && output.value < output.script_pubkey.dust_value().to_sat()
+            })
+            .map(|(i, output)| {
+                let address = script_address(&output.script_pubkey, network)
+                    .map_or(output.script_pubkey.to_hex_string(), |a| a.to_string());
+                format!(
+                    "output {} to {} is {} satoshi, below the dust threshold of {} satoshi",
+                    i,
+                    address,
+                    output.value,
+                    output.script_pubkey.dust_value().to_sat()
+                )
+            })
+    }
+
+    /// Sizes and amounts of the signed transaction, for a look before it's broadcast.
+    /// `input_amount` is the total of the spent outputs, which the transaction doesn't carry
+    pub fn summary(&self, input_amount: u64, change: Option<u64>) -> String {
+        let output_amount: u64 = self.0.output.iter().map(|output| output.value).sum();
+        let fee = input_amount - output_amount;
+        let vsize = self.0.vsize();
+
+        format!(
+            "vsize: {} vbytes\nweight: {} WU\ninputs: {} satoshi\noutputs: {} satoshi\n\
+             change: {}\nfee: {} satoshi ({:.2} sat/vB)",
+            vsize,
+            self.0.weight().to_wu(),
+            input_amount,
+            output_amount,
+            change.map_or("none".to_string(), |change| format!("{} satoshi", change)),
+            fee,
+            fee as f64 / vsize as f64
+        )
+    }
+
+    /// Returns a warning when the absolute locktime keeps the transaction out of the next block.
+    /// Height-based locktimes can only be checked when the tip height is known.
+    pub fn lock_time_warning(&self, tip_height: Option<u32>, tip_time: u32) -> Option<String> {
+        if !self.0.is_lock_time_enabled() {
+            return None;
+        }
+
+        match self.0.lock_time {
+            // A block at height H accepts locktimes below H
+            LockTime::Blocks(height) => {
+                let tip_height = tip_height?;
+                (height.to_consensus_u32() > tip_height).then(|| {
+                    format!(
+                        "this transaction cannot confirm until block {} (current tip: {})",
+                        height.to_consensus_u32() + 1,
+                        tip_height
+                    )
+                })
+            }
+            // BIP113: compared against the median time past of the chain tip
+            LockTime::Seconds(time) => (time.to_consensus_u32() >= tip_time).then(|| {
+                format!(
+                    "this transaction cannot confirm until time {} (current: {})",
+                    time.to_consensus_u32(),
+                    tip_time
+                )
+            }),
+        }
+    }
+
+    /// Breaks the serialization down into labelled fields, one per line, wrapping long fields.
+    /// Output scriptPubKeys are followed by their address on `network` when they have one.
+    pub fn annotated(&self, network: Network) -> String {
+        let addresses: Vec<(String, Address)> = self
+            .0
+            .output
+            .iter()
+            .enumerate()
+            .filter_map(|(i, output)| {
+                let address = script_address(&output.script_pubkey, network)?;
+                Some((format!("output {} scriptPubKey", i), address))
+            })
+            .collect();
+
+        self.fields()
+            .iter()
+            .flat_map(|(label, bytes)| {
+                let address = addresses
+                    .iter()
+                    .find(|(name, _)| name == label)
+                    .map(|(_, address)| address);
+                let last = bytes.len().saturating_sub(1) / 32;
+                bytes.chunks(32).enumerate().map(move |(i, chunk)| {
+                    let label = if i == 0 { label.as_str() } else { "" };
+                    let line = format!("{:<40}{}", label, hex::encode(chunk));
+                    match address {
+                        Some(address) if i == last => format!("{}  ({})", line, address),
+                        _ => line,
+                    }
+                })
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The serialization split into fields, which concatenate back to the raw transaction
+    fn fields(&self) -> Vec<(String, Vec<u8>)> {
+        let tx = &self.0;
+        let varint = |n: usize| serialize(&VarInt(n as u64));
+        let mut fields = vec![("version".to_string(), serialize(&tx.version))];
+
+        let has_witness = tx.input.iter().any(|input| !input.witness.is_empty());
+        if has_witness {
+            fields.push(("segwit marker".to_string(), vec![0x00]));
+            fields.push(("segwit flag".to_string(), vec![0x01]));
+        }
+
+        fields.push(("input count".to_string(), varint(tx.input.len())));
+        for (i, input) in tx.input.iter().enumerate() {
+            let script_sig = input.script_sig.as_bytes();
+            fields.extend([
+                (
+                    format!("input {} txid", i),
+                    serialize(&input.previous_output.txid),
+                ),
+                (
+                    format!("input {} vout", i),
+                    serialize(&input.previous_output.vout),
+                ),
+                (
+                    format!("input {} scriptSig length", i),
+                    varint(script_sig.len()),
+                ),
+            ]);
+            if !script_sig.is_empty() {
+                fields.push((format!("input {} scriptSig", i), script_sig.to_vec()));
+            }
+            fields.push((format!("input {} sequence", i), serialize(&input.sequence)));
+        }
+
+        fields.push(("output count".to_string(), varint(tx.output.len())));
+        for (i, output) in tx.output.iter().enumerate() {
+            let script_pubkey = output.script_pubkey.as_bytes();
+            fields.extend([
+                (format!("output {} value", i), serialize(&output.value)),
+                (
+                    format!("output {} scriptPubKey length", i),
+                    varint(script_pubkey.len()),
+                ),
+            ]);
+            if !script_pubkey.is_empty() {
+                fields.push((format!("output {} scriptPubKey", i), script_pubkey.to_vec()));
+            }
+        }
+
+        if has_witness {
+            for (i, input) in tx.input.iter().enumerate() {
+                fields.push((
+                    format!("input {} witness item count", i),
+                    varint(input.witness.len()),
+                ));
+                for (j, item) in input.witness.iter().enumerate() {
+                    fields.push((
+                        format!("input {} witness item {} length", i, j),
+                        varint(item.len()),
+                    ));
+                    if !item.is_empty() {
+                        fields.push((format!("input {} witness item {}", i, j), item.to_vec()));
+                    }
+                }
+            }
+        }
+
+        fields.push(("locktime".to_string(), serialize(&tx.lock_time)));
+        fields
+    }
+}
+
+/// Parses a WIF or a 32-byte hex private key (taken as a compressed key for `network`)
+fn parse_private_key(
+    key: &str,
+    format: KeyFormat,
+    network: Network,
+) -> Result<PrivateKey, anyhow::Error> {
+    match format.resolve(key) {
+        KeyFormat::Hex => {
+            let bytes = Vec::<u8>::from_hex(key)?;
+            ensure!(bytes.len() == 32, "expected 32 bytes, not {}", bytes.len());
+            Ok(PrivateKey::from_slice(&bytes, network)?)
+        }
+        _ => Ok(PrivateKey::from_wif(key)?),
+    }
+}
+
+/// Derives the P2PKH address of a private key the way `TxBuilder::new` derives its public key
+pub fn derive_address(
+    key: &str,
+    format: KeyFormat,
+    network: Option<Network>,
+    uncompressed: bool,
+) -> Result<Address, anyhow::Error> {
+    let mut private_key = parse_private_key(key, format, network.unwrap_or(Network::Testnet))
+        .context("invalid private key")?;
+    if let Some(network) = network {
+        // WIFs only tell mainnet from the rest
+        ensure!(
+            (private_key.network == Network::Bitcoin) == (network == Network::Bitcoin),
+            "the private key is not a {} key",
+            network
+        );
+    }
+    if uncompressed {
+        private_key.compressed = false;
+    }
+
+    let secp = Secp256k1::signing_only();
+    Ok(Address::p2pkh(
+        &private_key.public_key(&secp),
+        network.unwrap_or(private_key.network),
+    ))
+}
+
+/// Recovers the address of a standard scriptPubKey, `None` for others such as OP_RETURN
+pub fn script_address(script_pubkey: &Script, network: Network) -> Option<Address> {
+    Address::from_script(script_pubkey, network).ok()
+}
+
+/// Fisher-Yates shuffle, seeded by the OS randomness std seeds `HashMap`s with. Good enough to
+/// hide the change among the outputs; nothing secret depends on it
+fn shuffle<T>(items: &mut [T]) {
+    let state = RandomState::new();
+    for i in (1..items.len()).rev() {
+        let mut hasher = state.build_hasher();
+        hasher.write_usize(i);
+        items.swap(i, (hasher.finish() % (i as u64 + 1)) as usize);
+    }
+}
+
+/// Whether a UTXO locked to `script_pubkey` can belong to `source`: scriptPubKeys of the same
+/// standard type must be equal, and P2PKH and P2WPKH must hash the same key. Others, such as a
+/// P2SH-wrapped UTXO of a P2PKH address, can't be told apart from a mistake and are let through
+fn script_pubkeys_agree(script_pubkey: &Script, source: &Script) -> bool {
+    let key_hash = |script: &Script| match script.as_bytes() {
+        bytes if script.is_p2pkh() => Some(bytes[3..23].to_vec()),
+        bytes if script.is_v0_p2wpkh() => Some(bytes[2..22].to_vec()),
+        _ => None,
+    };
+    let kind = |script: &Script| [script.is_p2sh(), script.is_v0_p2wsh(), script.is_v1_p2tr()];
+
+    match (key_hash(script_pubkey), key_hash(source)) {
+        (Some(hash), Some(source_hash)) => hash == source_hash,
+        _ if kind(script_pubkey) == kind(source) && kind(source).contains(&true) => {
+            script_pubkey == source
+        }
+        _ => true,
+    }
+}
+
+/// Returns the scriptPubKey of an address, which must belong to `network`
+pub fn address_script_pubkey(address: &str, network: Network) -> Result<ScriptBuf, anyhow::Error> {
+    Ok(Address::from_str(address)?
+        .require_network(network)?
+        .script_pubkey())
+}
+
+/// Decodes a raw transaction given in hex
+pub fn decode_transaction(hex: &str) -> Result<Transaction, anyhow::Error> {
+    Ok(deserialize(&Vec::<u8>::from_hex(hex)?)?)
+}
+
+/// Fee and change the transaction would have at a given fee rate
+#[derive(Debug, PartialEq)]
+pub struct FeeEstimate {
+    pub fee_rate: f64,
+    pub fee: u64,
+    /// `None` when the UTXOs can't cover the payment and the fee
+    pub change: Option<u64>,
+    pub dust: bool,
+}
+
+/// A UTXO being spent. The amount is kept for every input, legacy ones included, so that
+/// sighash algorithms committing to it never find it missing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Utxo {
+    txid: Txid,
+    vout: u32,
+    amount: u64,
+    script_pubkey: ScriptBuf,
+    sighash_type: SighashType,
+    /// P2WPKH script a P2SH scriptPubKey commits to
+    redeem_script: Option<ScriptBuf>,
+    /// From `--sequence`, overriding the default of the transaction
+    sequence: Option<Sequence>,
+    /// From `--signing-script`: the legacy or P2WSH script signed instead of the scriptPubKey
+    signing_script: Option<ScriptBuf>,
+    /// Index of the key given with this input, which signs it in place of the keys for any input
+    key: Option<usize>,
+}
+
+impl Utxo {
+    /// Recovers what signing `input` took from the signature and scripts it carries
+    fn spent_by(input_index: usize, input: &TxIn, prevout: &Prevout) -> Result<Self, TxError> {
+        let txid = Txid::from_str(&prevout.txid).map_err(|_| TxError::InvalidTxid {
+            input_index,
+            txid: prevout.txid.clone(),
+        })?;
+        if (txid, prevout.vout) != (input.previous_output.txid, input.previous_output.vout) {
+            return Err(anyhow!(
+                "input {} spends {}, not the prevout {}:{}",
+                input_index,
+                input.previous_output,
+                prevout.txid,
+                prevout.vout
+            )
+            .into());
+        }
+        let script_pubkey = ScriptBuf::from_hex(&prevout.script_pubkey)
+            .with_context(|| format!("invalid scriptPubKey for input {}", input_index))?;
+
+        let pushes = input
+            .script_sig
+            .instructions()
+            .filter_map(|instruction| match instruction {
+                Ok(Instruction::PushBytes(bytes)) => Some(bytes.as_bytes()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let (redeem_script, signing_script) = if script_pubkey.is_p2sh() {
+            match pushes
+                .last()
+                .map(|last| ScriptBuf::from_bytes(last.to_vec()))
+            {
+                Some(script) if script.is_v0_p2wpkh() => (Some(script), None),
+                script => (None, script),
+            }
+        } else if script_pubkey.is_v0_p2wsh() {
+            (
+                None,
+                input
+                    .witness
+                    .last()
+                    .map(|last| ScriptBuf::from_bytes(last.to_vec())),
+            )
+        } else {
+            (None, None)
+        };
+        let witness_signed = script_pubkey.is_witness_program() || redeem_script.is_some();
+        let signature = match witness_signed {
+            true => input.witness.nth(0),
+            false => pushes.first().copied(),
+        };
+        // Anything unparsable is left to the signature check to report
+        let sighash_type = match (script_pubkey.is_v1_p2tr(), signature) {
+            (true, signature) => SighashType::Taproot(
+                signature
+                    .and_then(|signature| taproot::Signature::from_slice(signature).ok())
+                    .map_or(TapSighashType::Default, |signature| signature.hash_ty),
+            ),
+            (false, signature) => SighashType::Ecdsa(
+                signature
+                    .and_then(|signature| ecdsa::Signature::from_slice(signature).ok())
+                    .map_or(EcdsaSighashType::All, |signature| signature.hash_ty),
+            ),
+        };
+
+        Ok(Self {
+            txid,
+            vout: prevout.vout,
+            amount: prevout.amount,
+            script_pubkey,
+            sighash_type,
+            redeem_script,
+            sequence: None,
+            signing_script,
+            key: None,
+        })
+    }
+
+    /// Native or P2SH-wrapped P2WPKH, signed with a BIP143 sighash and a two-item witness
+    fn is_p2wpkh(&self) -> bool {
+        self.script_pubkey.is_v0_p2wpkh() || self.redeem_script.is_some()
+    }
+
+    /// Whether the signature and public key go in the witness rather than the script_sig
+    fn signs_witness(&self) -> bool {
+        self.is_p2wpkh() || self.script_pubkey.is_v0_p2wsh()
+    }
+}
+
+/// Sighash flag an input is signed with, of the kind its scriptPubKey calls for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SighashType {
+    Ecdsa(EcdsaSighashType),
+    Taproot(TapSighashType),
+}
+
+impl SighashType {
+    /// Parses a flag name like `SIGHASH_ALL|SIGHASH_ANYONECANPAY` or `all|anyonecanpay`,
+    /// defaulting to `SIGHASH_ALL` (`SIGHASH_DEFAULT` for Taproot)
+    fn parse(name: Option<&str>, script_pubkey: &ScriptBuf) -> Result<Self, anyhow::Error> {
+        let name = name.map(|name| {
+            name.split('|')
+                .map(|flag| {
+                    let flag = flag.trim().to_ascii_uppercase();
+                    match flag.starts_with("SIGHASH_") {
+                        true => flag,
+                        false => format!("SIGHASH_{}", flag),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("|")
+        });
+        Ok(match (script_pubkey.is_v1_p2tr(), name.as_deref()) {
+            (true, None) => SighashType::Taproot(TapSighashType::Default),
+            (true, Some(name)) => SighashType::Taproot(TapSighashType::from_str(name)?),
+            (false, None) => SighashType::Ecdsa(EcdsaSighashType::All),
+            (false, Some("SIGHASH_DEFAULT")) => {
+                bail!("`SIGHASH_DEFAULT` is only valid for Taproot inputs")
+            }
+            (false, Some(name)) => SighashType::Ecdsa(EcdsaSighashType::from_str(name)?),
+        })
     }
+
+    /// Whether the signature commits to the output at the input's own index only
+    fn is_single(&self) -> bool {
+        matches!(
+            self,
+            SighashType::Ecdsa(EcdsaSighashType::Single | EcdsaSighashType::SinglePlusAnyoneCanPay)
+                | SighashType::Taproot(
+                    TapSighashType::Single | TapSighashType::SinglePlusAnyoneCanPay
+                )
+        )
+    }
+}
+
+impl fmt::Display for SighashType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SighashType::Ecdsa(sighash_type) => write!(f, "{}", sighash_type),
+            SighashType::Taproot(sighash_type) => write!(f, "{}", sighash_type),
+        }
+    }
+}
+
+/// What became of the change when the transaction was created
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChangeOutcome {
+    Created,
+    /// Below the `--combine-dust` threshold and added to the destination
+    Combined(u64),
+    Dropped,
+    AddedToOutput,
+    /// No change with `--sweep`
+    Swept,
 }
 
-pub struct TxBuilder<C: Context + Signing> {
+/// What to build, for programs that use `TxBuilder` without the command line. Fields left
+/// out take the defaults of the command-line options
+#[derive(Debug, Clone, Default)]
+pub struct TxParams {
+    /// Where the change goes
+    pub source_address: String,
+    pub destination_address: String,
+    /// WIF or hex
+    pub private_key: SecretString,
+    pub send_amount: u64,
+    pub utxos: Vec<Prevout>,
+    /// Fixed fee in satoshi
+    pub fee: Option<u64>,
+    /// Fee rate in sat/vB, in place of `fee`
+    pub fee_rate: Option<f64>,
+}
+
+pub struct TxBuilder<C: Context + Signing + Verification> {
     transaction: Option<Transaction>,
-    private_key: PrivateKey,
-    public_key: PublicKey,
+    // Erased once signed and when the builder is dropped
+    keys: SecretKeys,
+    // Of the first key, which change outputs are derived from. `None` when building a PSBT
+    // without keys
+    public_key: Option<PublicKey>,
     secp: Secp256k1<C>,
-    utxo_txid: Txid,
-    utxo_tx_index: u32,
-    utxo_script_pubkey: ScriptBuf,
+    utxos: Vec<Utxo>,
     output_script_pubkey: ScriptBuf,
     change_script_pubkey: ScriptBuf,
     send_amount: u64,
-    utxo_amount: u64,
+    requested_amount: Option<u64>,
+    // Of `--output`, paid after the destination
+    outputs: Vec<TxOut>,
+    donation: Option<TxOut>,
+    op_return: Option<TxOut>,
+    version: i32,
+    lock_time: LockTime,
+    rbf: bool,
+    sign_inputs: Option<Vec<usize>>,
+    output_order: OutputOrder,
+    change_index: Option<usize>,
+    // Where the ordering put the destination output
+    destination_index: usize,
+    dust_policy: DustPolicy,
+    change_dust_to_fee_max: Option<u64>,
+    combine_dust: Option<Option<u64>>,
+    max_destination_amount: Option<u64>,
+    max_fee: Option<u64>,
+    force: bool,
+    fixed_fee: Option<u64>,
+    fee_rate: Option<f64>,
+    fee_percent: Option<f64>,
+    sweep: bool,
+    change_outcome: Option<ChangeOutcome>,
 }
 
-impl<C: Context + Signing> TxBuilder<C> {
-    pub fn new(args: &Args) -> Result<Self, anyhow::Error> {
-        let private_key = PrivateKey::from_wif(&args.private_key)?;
+impl<C: Context + Signing + Verification> TxBuilder<C> {
+    pub fn new(args: &Args) -> Result<Self, TxError> {
+        Self::new_with_prevouts(args, &args.prevouts()?)
+    }
+
+    pub fn from_params(params: &TxParams) -> Result<Self, TxError> {
+        if params.fee.is_some() && params.fee_rate.is_some() {
+            return Err(anyhow!("`fee` and `fee_rate` cannot both be given").into());
+        }
+        let args = Args {
+            source_address: params.source_address.clone(),
+            destination_address: Some(params.destination_address.clone()),
+            private_key: Some(params.private_key.clone()),
+            send_amount: Some(params.send_amount),
+            fee: params.fee,
+            fee_rate: params.fee_rate,
+            ..Default::default()
+        };
+        Self::new_with_prevouts(&args, &params.utxos)
+    }
 
+    /// Like `new`, but spending `prevouts` instead of the UTXOs given in `args`
+    pub fn new_with_prevouts(args: &Args, prevouts: &[Prevout]) -> Result<Self, TxError> {
+        let network = args.network().map(Network::from);
+        let parse_key = |name: &str, key: &str| -> Result<PrivateKey, anyhow::Error> {
+            let format = match args.private_key_format.resolve(key) {
+                KeyFormat::Hex => "hex",
+                _ => "WIF",
+            };
+            let mut key = parse_private_key(
+                key,
+                args.private_key_format,
+                network.unwrap_or(Network::Testnet),
+            )
+            .map_err(|e| TxError::InvalidPrivateKey {
+                name: name.to_string(),
+                format,
+                reason: e.to_string(),
+            })?;
+            if args.uncompressed {
+                key.compressed = false;
+            }
+            // WIFs only tell mainnet from the rest
+            if let Some(network) = network {
+                ensure!(
+                    (key.network == Network::Bitcoin) == (network == Network::Bitcoin),
+                    "{} is for {}, not {}",
+                    name,
+                    key.network,
+                    network
+                );
+            }
+            Ok(key)
+        };
+        let mut keys = args
+            .private_keys()?
+            .iter()
+            .map(|(name, key)| parse_key(name, key))
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+        // Keys given with an input come after those for any input, and sign that input alone
+        let input_keys = prevouts
+            .iter()
+            .enumerate()
+            .map(|(i, prevout)| {
+                let Some(key) = &prevout.private_key else {
+                    return Ok(None);
+                };
+                keys.push(parse_key(&format!("the private key of input {}", i), key)?);
+                Ok(Some(keys.len() - 1))
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+        // Addresses must belong to `--network` when it is given
+        let parse_address = |address: &str, name: &str| -> Result<Address, anyhow::Error> {
+            let address = Address::from_str(address)?;
+            match network {
+                Some(network) => address
+                    .require_network(network)
+                    .with_context(|| format!("{} is not a {} address", name, network)),
+                None => Ok(address.assume_checked()),
+            }
+        };
         let secp = Secp256k1::gen_new();
-        let public_key = private_key.public_key(&secp);
+        let public_key = keys.first().map(|key| key.public_key(&secp));
+        for key in &keys {
+            debug!("public key: {}", key.public_key(&secp));
+        }
+
+        let given_redeem_script = match &args.redeem_script {
+            Some(hex) => Some(ScriptBuf::from_bytes(
+                Vec::<u8>::from_hex(hex).context("`--redeem-script` must be hexadecimals")?,
+            )),
+            None => None,
+        };
+        let signing_script = match &args.signing_script {
+            Some(hex) => Some(ScriptBuf::from_bytes(
+                Vec::<u8>::from_hex(hex).context("`--signing-script` must be hexadecimals")?,
+            )),
+            None => None,
+        };
+        let utxos = prevouts
+            .iter()
+            .enumerate()
+            .map(|(i, prevout)| {
+                let txid = Txid::from_str(&prevout.txid).map_err(|_| TxError::InvalidTxid {
+                    input_index: i,
+                    txid: prevout.txid.clone(),
+                })?;
+
+                let bytes = Vec::<u8>::from_hex(&prevout.script_pubkey)?;
+                let script_pubkey = ScriptBuf::from_bytes(bytes);
+                let sighash_type =
+                    SighashType::parse(prevout.sighash.as_deref(), &script_pubkey)
+                        .with_context(|| format!("invalid sighash type for input {}", i))?;
 
-        let mut bytes = Vec::<u8>::from_hex(&args.utxo_txid)?;
-        bytes.reverse();
-        let utxo_txid = Txid::from_slice(&bytes)?;
-        let utxo_tx_index = args.utxo_tx_index;
+                let amount = match args.input_amount.get(i) {
+                    Some(&amount) => {
+                        if amount != prevout.amount {
+                            warning!(
+                                "input {} amount mismatch: {} satoshi given, using {} satoshi \
+                                 from `--input-amount`",
+                                i,
+                                prevout.amount,
+                                amount
+                            );
+                        }
+                        amount
+                    }
+                    None => prevout.amount,
+                };
 
-        let bytes = Vec::<u8>::from_hex(&args.utxo_script_pubkey)?;
-        let utxo_script_pubkey = ScriptBuf::from_bytes(bytes);
+                if let Some(signing_script) = &signing_script {
+                    let committed = if script_pubkey.is_p2sh() {
+                        ScriptBuf::new_p2sh(&signing_script.script_hash()) == script_pubkey
+                    } else if script_pubkey.is_v0_p2wsh() {
+                        ScriptBuf::new_v0_p2wsh(&signing_script.wscript_hash()) == script_pubkey
+                    } else {
+                        ensure!(
+                            !script_pubkey.is_witness_program(),
+                            "`--signing-script` only applies to legacy, P2SH and P2WSH inputs, \
+                             not input {}",
+                            i
+                        );
+                        true
+                    };
+                    ensure!(
+                        committed,
+                        "`--signing-script` does not match the scriptPubKey of input {}",
+                        i
+                    );
+                }
+
+                // P2SH-P2WPKH, whose redeem script is either given or derived from a key
+                let redeem_script = if script_pubkey.is_p2sh() && signing_script.is_none() {
+                    let commits = |redeem_script: &ScriptBuf| {
+                        ScriptBuf::new_p2sh(&redeem_script.script_hash()) == script_pubkey
+                    };
+                    if let Some(redeem_script) = &given_redeem_script {
+                        ensure!(
+                            commits(redeem_script),
+                            "`--redeem-script` does not match the scriptPubKey of input {}",
+                            i
+                        );
+                    }
+                    given_redeem_script.clone().or_else(|| {
+                        keys.iter()
+                            .filter_map(|key| key.public_key(&secp).wpubkey_hash())
+                            .map(|hash| ScriptBuf::new_v0_p2wpkh(&hash))
+                            .find(commits)
+                    })
+                } else {
+                    None
+                };
+
+                Ok(Utxo {
+                    txid,
+                    vout: prevout.vout,
+                    amount,
+                    script_pubkey,
+                    sighash_type,
+                    redeem_script,
+                    sequence: args.sequence.get(i).copied().map(Sequence),
+                    signing_script: signing_script.clone(),
+                    key: input_keys[i],
+                })
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
 
         // ScriptPubKey for destination output
-        let dest_address = Address::from_str(&args.destination_address)?.assume_checked();
-        let output_script_pubkey = dest_address.script_pubkey();
+        let output_script_pubkey = match (&args.destination_address, &args.destination_script) {
+            (_, Some(hex)) => {
+                ScriptBuf::from_hex(hex).context("`--destination-script` must be hexadecimals")?
+            }
+            (Some(address), None) => {
+                parse_address(address, "`--destination-address`")?.script_pubkey()
+            }
+            (None, None) => {
+                return Err(anyhow!(
+                    "`--destination-address` or `--destination-script` is required"
+                )
+                .into())
+            }
+        };
+
+        // Additional outputs
+        let outputs = args
+            .outputs
+            .iter()
+            .enumerate()
+            .map(|(i, payment)| {
+                Ok(TxOut {
+                    value: payment.amount,
+                    script_pubkey: parse_address(&payment.address, &format!("`--output` {}", i))?
+                        .script_pubkey(),
+                })
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+        // Donation output
+        let donation = match &args.donate {
+            Some(donation) => Some(TxOut {
+                value: donation.amount,
+                script_pubkey: parse_address(&donation.address, "`--donate`")?.script_pubkey(),
+            }),
+            None => None,
+        };
+
+        // OP_RETURN output
+        let op_return = match &args.op_return {
+            Some(data) => Some(TxOut {
+                value: 0,
+                script_pubkey: ScriptBuf::new_op_return(
+                    &PushBytesBuf::try_from(
+                        Vec::<u8>::from_hex(data).context("`--op-return` must be hexadecimals")?,
+                    )
+                    .context("`--op-return` is too long")?,
+                ),
+            }),
+            None => None,
+        };
 
         // ScriptPubKey for change output
-        let source_address = Address::from_str(&args.source_address)?.assume_checked();
-        let change_script_pubkey = source_address.script_pubkey();
+        let source_address = parse_address(&args.source_address, "`--source-address`")?;
+        // A key for another address would only be noticed once the signature fails to verify.
+        // Source types no single key derives (e.g. P2WSH) can't be checked
+        let source_script_pubkey = source_address.script_pubkey();
+        let derived = keys
+            .iter()
+            .map(|key| {
+                Self::derive_script_pubkey(&secp, &key.public_key(&secp), &source_script_pubkey)
+            })
+            .collect::<Vec<_>>();
+        // A custom signing script can lock the source to anything, so it isn't derived from a key
+        if args.signing_script.is_none()
+            && !derived.contains(&Some(source_script_pubkey.clone()))
+            && derived.iter().any(Option::is_some)
+        {
+            let controlled = derived
+                .iter()
+                .flatten()
+                .filter_map(|script_pubkey| script_address(script_pubkey, source_address.network))
+                .map(|address| address.to_string())
+                .collect::<Vec<_>>();
+            return Err(anyhow!(
+                "the private key controls {}, not `--source-address` {}",
+                controlled.join(", "),
+                args.source_address
+            )
+            .into());
+        }
+        // A copy-pasted scriptPubKey of another address would make the input unspendable
+        if let Some(given) = &args.utxo_script_pubkey {
+            let given = ScriptBuf::from_hex(given)
+                .context("`--utxo-script-pubkey` must be hexadecimals")?;
+            if !script_pubkeys_agree(&given, &source_script_pubkey) {
+                return Err(anyhow!(
+                    "`--utxo-script-pubkey` {} does not belong to `--source-address` {}",
+                    given.to_hex_string(),
+                    args.source_address
+                )
+                .into());
+            }
+        }
+        let change_script_pubkey = match (&args.change_address, args.change_matches, &public_key) {
+            (Some(change_address), _, _) => {
+                parse_address(change_address, "`--change-address`")?.script_pubkey()
+            }
+            (None, ChangeMatches::Destination, Some(public_key)) => {
+                Self::derive_script_pubkey(&secp, public_key, &output_script_pubkey).unwrap_or_else(
+                    || {
+                        if !public_key.compressed
+                            && (output_script_pubkey.is_v0_p2wpkh()
+                                || output_script_pubkey.is_p2sh())
+                        {
+                            warning!(
+                                "an uncompressed key can't receive SegWit change like the \
+                                 destination, so the change goes back to `--source-address`"
+                            );
+                        }
+                        source_address.script_pubkey()
+                    },
+                )
+            }
+            _ => source_address.script_pubkey(),
+        };
 
-        Ok(Self {
+        let mut tx_builder = Self {
             transaction: None,
-            private_key,
+            keys: SecretKeys::new(keys),
             public_key,
             secp,
-            utxo_txid,
-            utxo_tx_index,
-            utxo_script_pubkey,
+            utxos,
             output_script_pubkey,
             change_script_pubkey,
-            send_amount: args.send_amount,
-            utxo_amount: args.utxo_amount,
-        })
+            send_amount: args.send_amount.unwrap_or_default(),
+            requested_amount: args.send_amount,
+            outputs,
+            donation,
+            op_return,
+            version: args.tx_version.unwrap_or(1),
+            lock_time: LockTime::from_consensus(args.lock_time),
+            rbf: args.rbf,
+            sign_inputs: args.sign_inputs.clone(),
+            output_order: args.output_order,
+            change_index: args.change_index,
+            destination_index: 0,
+            dust_policy: args.dust_policy,
+            change_dust_to_fee_max: args.change_dust_to_fee_max,
+            combine_dust: args.combine_dust,
+            max_destination_amount: args.max_destination_amount,
+            max_fee: args.max_fee,
+            force: args.force,
+            fixed_fee: args.fee,
+            fee_rate: args.fee_rate,
+            fee_percent: args.fee_percent,
+            sweep: args.sweep,
+            change_outcome: None,
+        };
+
+        tx_builder.check_sequences()?;
+        if let Some(reserve) = args.reserve {
+            tx_builder.send_amount = tx_builder.reserve_send_amount(reserve)?;
+        }
+        if args.sweep {
+            tx_builder.send_amount = tx_builder.sweep_send_amount()?;
+        }
+
+        Ok(tx_builder)
     }
 
-    pub fn create_without_sig(&mut self) -> Result<&mut Self, anyhow::Error> {
+    pub fn create_without_sig(&mut self) -> Result<&mut Self, TxError> {
+        let mut output = vec![TxOut {
+            value: self.send_amount,
+            script_pubkey: self.output_script_pubkey.clone(),
+        }];
+        output.extend(self.outputs.iter().cloned());
+        output.extend(self.donation.clone());
+        output.extend(self.op_return.clone());
+
+        // Change output
+        let change_amount = self.calc_change_amount()?;
+        let dust_threshold = self.change_script_pubkey.dust_value().to_sat();
+        info!(
+            "payment: {} satoshi, other outputs: {} satoshi, donation: {} satoshi, fee: {} satoshi, \
+             change: {} satoshi",
+            self.send_amount,
+            self.outputs_amount(),
+            self.donation_amount(),
+            self.calc_fee(),
+            change_amount
+        );
+        if let Some(fee_percent) = self.fee_percent {
+            let fee = self.calc_fee();
+            if self.percent_fee(fee_percent) < fee {
+                warning!(
+                    "{}% of the payment is below the minimum relay fee, raised to {} satoshi",
+                    fee_percent,
+                    fee
+                );
+            }
+            info!(
+                "fee: {} satoshi, effective rate: {:.2} sat/vB",
+                fee,
+                fee as f64 / self.estimated_vsize() as f64
+            );
+        }
+        let combine_threshold = self
+            .combine_dust
+            .map(|threshold| threshold.unwrap_or(dust_threshold))
+            .filter(|&threshold| change_amount < threshold);
+        let has_change = combine_threshold.is_none() && change_amount >= dust_threshold;
+        if self.sweep {
+            self.change_outcome = Some(ChangeOutcome::Swept);
+        } else if has_change {
+            output.push(TxOut {
+                value: change_amount,
+                script_pubkey: self.change_script_pubkey.clone(),
+            });
+            self.change_outcome = Some(ChangeOutcome::Created);
+        } else if let Some(threshold) = combine_threshold {
+            debug!(
+                "combining change of {} satoshi into the destination",
+                change_amount
+            );
+            output[0].value += change_amount;
+            self.change_outcome = Some(ChangeOutcome::Combined(threshold));
+        } else {
+            self.change_outcome = Some(match self.dust_policy {
+                DustPolicy::Drop => {
+                    let max = self.change_dust_to_fee_max.unwrap_or(dust_threshold);
+                    if change_amount > max {
+                        return Err(anyhow!(
+                            "dust change of {} satoshi exceeds `--change-dust-to-fee-max` of {} \
+                             satoshi, refusing to add it to the fee",
+                            change_amount,
+                            max
+                        )
+                        .into());
+                    }
+                    debug!("dropping dust change of {} satoshi", change_amount);
+                    ChangeOutcome::Dropped
+                }
+                DustPolicy::Fail => {
+                    return Err(TxError::DustOutput {
+                        output: "change".to_string(),
+                        amount: change_amount,
+                        threshold: dust_threshold,
+                    })
+                }
+                DustPolicy::AddToOutput => {
+                    debug!(
+                        "adding dust change of {} satoshi to the destination",
+                        change_amount
+                    );
+                    output[0].value += change_amount;
+                    ChangeOutcome::AddedToOutput
+                }
+            });
+        }
+        if let Some(max) = self.max_destination_amount {
+            if output[0].value > max {
+                return Err(anyhow!(
+                    "destination output of {} satoshi ({} satoshi plus {} satoshi of change) \
+                     exceeds `--max-destination-amount` of {} satoshi",
+                    output[0].value,
+                    self.send_amount,
+                    output[0].value - self.send_amount,
+                    max
+                )
+                .into());
+            }
+        }
+        if let Some(warning) = self.send_amount_warning(output[0].value) {
+            warning!("{}", warning);
+        }
+        // Dust dropped into the fee is bounded by the dust policy, so only the computed fee counts
+        if let Some(error) = self.excessive_fee_error(self.calc_fee()) {
+            if !self.force {
+                return Err(anyhow!("{} (use `--force` to build it anyway)", error).into());
+            }
+            warning!("{}", error);
+        }
+
+        if self.lock_time != LockTime::ZERO
+            && !self.rbf
+            && self.utxos.iter().any(|utxo| utxo.sequence.is_none())
+        {
+            warning!(
+                "locktime {} is set, so the input sequences are {:#x} instead of {:#x} to enforce it",
+                self.lock_time,
+                self.sequence().0,
+                Sequence::MAX.0
+            );
+        }
+
+        // The order is part of what gets signed, so it's settled here
+        let pinned_change = match self.change_index {
+            Some(index) => {
+                if !has_change || self.sweep {
+                    return Err(anyhow!(
+                        "`--change-index` {} is given, but the transaction has no change output",
+                        index
+                    )
+                    .into());
+                }
+                if index >= output.len() {
+                    return Err(anyhow!(
+                        "`--change-index` {} is out of range for {} outputs",
+                        index,
+                        output.len()
+                    )
+                    .into());
+                }
+                output.pop().map(|change| (index, change))
+            }
+            None => None,
+        };
+        // Outputs keep their index from before the ordering, where the destination is first
+        let mut output: Vec<(usize, TxOut)> = output.into_iter().enumerate().collect();
+        match self.output_order {
+            OutputOrder::AsGiven | OutputOrder::ChangeLast => {}
+            OutputOrder::ChangeFirst => {
+                if has_change && pinned_change.is_none() {
+                    output.rotate_right(1)
+                }
+            }
+            OutputOrder::Bip69 => output.sort_by(|(_, a), (_, b)| {
+                a.value
+                    .cmp(&b.value)
+                    .then_with(|| a.script_pubkey.as_bytes().cmp(b.script_pubkey.as_bytes()))
+            }),
+            OutputOrder::Random => shuffle(&mut output),
+        }
+        if let Some((index, change)) = pinned_change {
+            output.insert(index, (output.len(), change));
+        }
+        self.destination_index = output.iter().position(|(i, _)| *i == 0).unwrap_or(0);
+        let output: Vec<TxOut> = output.into_iter().map(|(_, output)| output).collect();
+
         self.transaction = Some(Transaction {
-            version: 1,
-            lock_time: LockTime::ZERO,
-            input: vec![TxIn {
-                previous_output: OutPoint {
-                    txid: self.utxo_txid,
-                    vout: self.utxo_tx_index,
-                },
-                script_sig: ScriptBuf::new(),
-                sequence: Sequence::MAX,
-                witness: Witness::new(),
-            }],
-            output: vec![
-                TxOut {
-                    value: self.send_amount,
-                    script_pubkey: self.output_script_pubkey.clone(),
-                },
-                // Change output
-                TxOut {
-                    value: self.calc_change_amount(),
-                    script_pubkey: self.change_script_pubkey.clone(),
-                },
-            ],
+            version: self.version,
+            lock_time: self.lock_time,
+            input: self
+                .utxos
+                .iter()
+                .map(|utxo| TxIn {
+                    previous_output: OutPoint {
+                        txid: utxo.txid,
+                        vout: utxo.vout,
+                    },
+                    script_sig: ScriptBuf::new(),
+                    sequence: self.input_sequence(utxo),
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output,
         });
 
+        let transaction = self.transaction.as_ref().unwrap();
+        debug!(
+            "created unsigned transaction with {} inputs and {} outputs",
+            transaction.input.len(),
+            transaction.output.len()
+        );
+        trace!(
+            "unsigned transaction: {}",
+            hex::encode(serialize(transaction))
+        );
+        if self.fixed_fee.is_some() {
+            if let Some(shortfall) = self.relay_fee_shortfall() {
+                warning!(
+                    "{}, so it only relays with a child paying for it (CPFP)",
+                    shortfall
+                );
+            }
+        }
+
         Ok(self)
     }
 
-    pub fn sign(&mut self) -> Result<&mut Self, anyhow::Error> {
-        let transaction = self.transaction.clone().unwrap();
-        let sighash = SighashCache::new(&transaction).legacy_signature_hash(
-            INPUT_INDEX,
-            &self.utxo_script_pubkey,
-            SIGHASH_ALL as u32,
-        )?;
-        let message = secp256k1::Message::from_slice(&sighash[..])?;
-        let secret_key = SecretKey::from_slice(&self.private_key.to_bytes())?;
-        let signature = self.secp.sign_ecdsa(&message, &secret_key);
+    /// Signs the inputs, then erases the private keys, so a builder signs once
+    pub fn sign(&mut self) -> Result<&mut Self, TxError> {
+        let transaction = self.created("sign")?.clone();
+        let mut cache = SighashCache::new(&transaction);
+        let prevouts = self.prevouts();
+
+        for (input_index, utxo) in self.utxos.iter().enumerate() {
+            // Inputs not selected are left unsigned for another party to complete
+            if let Some(sign_inputs) = &self.sign_inputs {
+                if !sign_inputs.contains(&input_index) {
+                    continue;
+                }
+            }
+
+            let private_key = self.signing_key(input_index, utxo)?;
+            let public_key = private_key.public_key(&self.secp);
+            let secret_key = ScopedSecretKey::new(private_key.inner);
+            let message = Self::signature_hash(&mut cache, input_index, utxo, &prevouts)?;
+            debug!("sighash for input {}: {}", input_index, message);
+            let input = &mut self.transaction.as_mut().unwrap().input[input_index];
+
+            if utxo.script_pubkey.is_v1_p2tr() {
+                // BIP86 key-path spend: sign with the internal key tweaked by an empty merkle root
+                let key_pair = KeyPair::from_secret_key(&self.secp, &secret_key)
+                    .tap_tweak(&self.secp, None)
+                    .to_inner();
+                let SighashType::Taproot(hash_ty) = utxo.sighash_type else {
+                    unreachable!("Taproot inputs get Taproot sighash types")
+                };
+                let signature = taproot::Signature {
+                    sig: self.secp.sign_schnorr_no_aux_rand(&message, &key_pair),
+                    hash_ty,
+                };
+                trace!(
+                    "witness for input {}: {}",
+                    input_index,
+                    hex::encode(signature.to_vec())
+                );
+                input.witness = Witness::from_slice(&[signature.to_vec()]);
+            } else if utxo.is_p2wpkh() {
+                let SighashType::Ecdsa(hash_ty) = utxo.sighash_type else {
+                    unreachable!("non-Taproot inputs get ECDSA sighash types")
+                };
+                if !public_key.compressed {
+                    return Err(TxError::SigningFailed {
+                        input_index,
+                        reason: "P2WPKH needs a compressed key".to_string(),
+                    });
+                }
+                let signature = ecdsa::Signature {
+                    sig: self.secp.sign_ecdsa(&message, &secret_key),
+                    hash_ty,
+                };
+                trace!(
+                    "witness for input {}: {} {}",
+                    input_index,
+                    hex::encode(signature.to_vec()),
+                    public_key
+                );
+                input.witness = Witness::from_slice(&[signature.to_vec(), public_key.to_bytes()]);
+                if let Some(redeem_script) = &utxo.redeem_script {
+                    input.script_sig = Self::p2sh_script_sig(redeem_script);
+                }
+            } else if utxo.script_pubkey.is_v0_p2wsh() {
+                let SighashType::Ecdsa(hash_ty) = utxo.sighash_type else {
+                    unreachable!("non-Taproot inputs get ECDSA sighash types")
+                };
+                let Some(witness_script) = &utxo.signing_script else {
+                    unreachable!("P2WSH sighashes need `--signing-script`")
+                };
+                let signature = ecdsa::Signature {
+                    sig: self.secp.sign_ecdsa(&message, &secret_key),
+                    hash_ty,
+                };
+                trace!(
+                    "witness for input {}: {} {} {}",
+                    input_index,
+                    hex::encode(signature.to_vec()),
+                    public_key,
+                    witness_script.to_hex_string()
+                );
+                input.witness = Witness::from_slice(&[
+                    signature.to_vec(),
+                    public_key.to_bytes(),
+                    witness_script.to_bytes(),
+                ]);
+            } else {
+                let SighashType::Ecdsa(sighash_type) = utxo.sighash_type else {
+                    unreachable!("non-Taproot inputs get ECDSA sighash types")
+                };
+                let signature = self.secp.sign_ecdsa(&message, &secret_key);
+                let mut script_sig = Self::create_script_sig(&signature, &public_key, sighash_type);
+                // P2SH needs the redeem script to run last
+                if let Some(redeem_script) = utxo
+                    .signing_script
+                    .as_ref()
+                    .filter(|_| utxo.script_pubkey.is_p2sh())
+                {
+                    let mut push = ScriptBuf::new();
+                    push.push_slice(PushBytesBuf::try_from(redeem_script.to_bytes()).map_err(
+                        |_| TxError::SigningFailed {
+                            input_index,
+                            reason: "the signing script is too large to push".to_string(),
+                        },
+                    )?);
+                    script_sig.extend_from_slice(push.as_bytes());
+                }
+                let script_sig = ScriptBuf::from(script_sig);
+                trace!(
+                    "script_sig for input {}: {} ({})",
+                    input_index,
+                    script_sig.to_hex_string(),
+                    script_sig.to_asm_string()
+                );
+                input.script_sig = script_sig;
+            }
+        }
 
-        let script_sig = Self::create_script_sig(&signature, &self.public_key);
-        self.transaction.as_mut().unwrap().input[0].script_sig = ScriptBuf::from(script_sig);
+        let transaction = self.transaction.as_ref().unwrap();
+        debug!(
+            "signed transaction: {} vbytes, {} WU",
+            transaction.vsize(),
+            transaction.weight().to_wu()
+        );
+        self.keys.erase();
 
         Ok(self)
     }
 
-    pub fn build(&self) -> Tx {
-        Tx(self.transaction.clone().unwrap())
+    /// Recomputes each sighash and checks the signatures of the signed inputs against the
+    /// scriptPubKeys they spend, so that a signing bug fails here instead of on the network
+    pub fn verify(&self) -> Result<&Self, TxError> {
+        let transaction = self.created("verify")?;
+        let mut cache = SighashCache::new(transaction);
+        let prevouts = self.prevouts();
+
+        for (input_index, (utxo, input)) in self.utxos.iter().zip(&transaction.input).enumerate() {
+            if let Some(sign_inputs) = &self.sign_inputs {
+                if !sign_inputs.contains(&input_index) {
+                    continue;
+                }
+            }
+
+            Self::verify_input(&self.secp, &mut cache, input_index, utxo, input, &prevouts)?;
+        }
+
+        Ok(self)
     }
 
-    fn calc_change_amount(&self) -> u64 {
-        self.utxo_amount - self.send_amount - FEE
+    /// Checks the signature of one input against the scriptPubKey it spends
+    fn verify_input(
+        secp: &Secp256k1<C>,
+        cache: &mut SighashCache<&Transaction>,
+        input_index: usize,
+        utxo: &Utxo,
+        input: &TxIn,
+        prevouts: &[TxOut],
+    ) -> Result<(), TxError> {
+        let message = Self::signature_hash(cache, input_index, utxo, prevouts)?;
+        let error = if utxo.script_pubkey.is_v1_p2tr() {
+            let output_key =
+                secp256k1::XOnlyPublicKey::from_slice(&utxo.script_pubkey.as_bytes()[2..])
+                    .map_err(|e| TxError::SigningFailed {
+                        input_index,
+                        reason: format!("invalid Taproot output key ({})", e),
+                    })?;
+            match input.witness.nth(0).map(taproot::Signature::from_slice) {
+                Some(Ok(signature)) => Self::signature_error(
+                    SighashType::Taproot(signature.hash_ty),
+                    utxo.sighash_type,
+                    secp.verify_schnorr(&signature.sig, &message, &output_key)
+                        .is_ok(),
+                ),
+                _ => Some("no valid Schnorr signature in the witness"),
+            }
+        } else {
+            let (signature, public_key) = if utxo.signs_witness() {
+                (input.witness.nth(0), input.witness.nth(1))
+            } else {
+                let mut pushes =
+                    input
+                        .script_sig
+                        .instructions()
+                        .filter_map(|instruction| match instruction {
+                            Ok(Instruction::PushBytes(bytes)) => Some(bytes.as_bytes()),
+                            _ => None,
+                        });
+                (pushes.next(), pushes.next())
+            };
+            let script_sig_error = utxo
+                .redeem_script
+                .as_ref()
+                .filter(|redeem_script| input.script_sig != Self::p2sh_script_sig(redeem_script))
+                .map(|_| "the script_sig does not push the redeem script")
+                .or_else(|| {
+                    let signing_script = utxo.signing_script.as_ref()?.as_bytes();
+                    let last = if utxo.script_pubkey.is_v0_p2wsh() {
+                        input.witness.last()
+                    } else if utxo.script_pubkey.is_p2sh() {
+                        input
+                            .script_sig
+                            .instructions()
+                            .filter_map(|instruction| match instruction {
+                                Ok(Instruction::PushBytes(bytes)) => Some(bytes.as_bytes()),
+                                _ => None,
+                            })
+                            .last()
+                    } else {
+                        return None;
+                    };
+                    (last != Some(signing_script))
+                        .then_some("the signing script is not pushed last")
+                });
+            let signature = signature.map(ecdsa::Signature::from_slice);
+            let public_key = public_key.map(PublicKey::from_slice);
+            match (signature, public_key) {
+                _ if script_sig_error.is_some() => script_sig_error,
+                (Some(Ok(signature)), Some(Ok(public_key))) => {
+                    // Other legacy scripts are only checked by the signature itself
+                    let script_pubkey = if utxo.is_p2wpkh() {
+                        Self::derive_script_pubkey(secp, &public_key, &utxo.script_pubkey)
+                    } else {
+                        Some(ScriptBuf::new_p2pkh(&public_key.pubkey_hash()))
+                    };
+                    if (utxo.is_p2wpkh() || utxo.script_pubkey.is_p2pkh())
+                        && script_pubkey.as_ref() != Some(&utxo.script_pubkey)
+                    {
+                        Some("the public key does not match the scriptPubKey")
+                    } else {
+                        Self::signature_error(
+                            SighashType::Ecdsa(signature.hash_ty),
+                            utxo.sighash_type,
+                            secp.verify_ecdsa(&message, &signature.sig, &public_key.inner)
+                                .is_ok(),
+                        )
+                    }
+                }
+                _ => Some("no valid signature and public key"),
+            }
+        };
+        if let Some(error) = error {
+            return Err(TxError::SigningFailed {
+                input_index,
+                reason: format!(
+                    "signature does not verify against scriptPubKey {}: {}",
+                    utxo.script_pubkey.to_hex_string(),
+                    error
+                ),
+            });
+        }
+        Ok(())
     }
 
-    fn create_script_sig(signature: &Signature, public_key: &PublicKey) -> Vec<u8> {
-        let mut script_sig = Vec::new();
+    fn signature_error(
+        hash_ty: SighashType,
+        expected: SighashType,
+        verified: bool,
+    ) -> Option<&'static str> {
+        if hash_ty != expected {
+            Some("the signature commits to a different sighash type")
+        } else if !verified {
+            Some("the signature does not match the sighash")
+        } else {
+            None
+        }
+    }
 
-        let serialized_sig = signature.serialize_der();
-        script_sig.push((serialized_sig.len() as u8) + size_of_val(&SIGHASH_ALL) as u8);
-        script_sig.extend_from_slice(&serialized_sig);
-        script_sig.push(SIGHASH_ALL);
+    /// Collects what a reviewer needs to recompute the sighashes and verify the signatures
+    /// without network access: the unsigned transaction, the spent outputs and, per input,
+    /// the sighash type, the sighash and the signature with its public key
+    pub fn verification_bundle(&self) -> Result<Value, TxError> {
+        let signed = self.created("verification_bundle")?;
+        // Inputs `--sign-inputs` leaves out stay unsigned, but some input must be signed
+        let unsigned = signed
+            .input
+            .iter()
+            .all(|input| input.script_sig.is_empty() && input.witness.is_empty());
+        if unsigned {
+            return Err(anyhow!(
+                "`verification_bundle` needs the transaction signed by `sign` first"
+            )
+            .into());
+        }
+        let mut unsigned = signed.clone();
+        for input in &mut unsigned.input {
+            input.script_sig = ScriptBuf::new();
+            input.witness = Witness::new();
+        }
+        let mut cache = SighashCache::new(&unsigned);
+        let prevouts = self.prevouts();
+        let string = |s: String| Value::String(s);
+        let number = |n: u64| Value::Number(n.to_string());
+        let or_null = |s: Option<String>| s.map_or(Value::Null, Value::String);
 
-        let serialized_pubkey = public_key.to_bytes();
-        script_sig.push(serialized_pubkey.len() as u8);
-        script_sig.extend_from_slice(&serialized_pubkey);
+        let inputs = self
+            .utxos
+            .iter()
+            .zip(&signed.input)
+            .enumerate()
+            .map(|(input_index, (utxo, input))| {
+                let (signature, public_key) = if utxo.script_pubkey.is_v1_p2tr() {
+                    // The output key in the scriptPubKey is what the signature verifies against
+                    (input.witness.nth(0).map(hex::encode), None)
+                } else if utxo.signs_witness() {
+                    (
+                        input.witness.nth(0).map(hex::encode),
+                        input.witness.nth(1).map(hex::encode),
+                    )
+                } else {
+                    let mut pushes =
+                        input.script_sig.instructions().filter_map(
+                            |instruction| match instruction {
+                                Ok(Instruction::PushBytes(bytes)) => Some(hex::encode(bytes)),
+                                _ => None,
+                            },
+                        );
+                    (pushes.next(), pushes.next())
+                };
+                let sighash = Self::signature_hash(&mut cache, input_index, utxo, &prevouts)
+                    .ok()
+                    .map(|message| message.to_string());
 
-        script_sig
+                Value::Object(vec![
+                    ("txid".to_string(), string(utxo.txid.to_string())),
+                    ("vout".to_string(), number(utxo.vout.into())),
+                    ("amount".to_string(), number(utxo.amount)),
+                    (
+                        "script_pubkey".to_string(),
+                        string(utxo.script_pubkey.to_hex_string()),
+                    ),
+                    (
+                        "sighash_type".to_string(),
+                        string(utxo.sighash_type.to_string()),
+                    ),
+                    ("sighash".to_string(), or_null(sighash)),
+                    ("signature".to_string(), or_null(signature)),
+                    ("public_key".to_string(), or_null(public_key)),
+                ])
+            })
+            .collect();
+
+        Ok(Value::Object(vec![
+            (
+                "unsigned_transaction".to_string(),
+                string(hex::encode(serialize(&unsigned))),
+            ),
+            (
+                "signed_transaction".to_string(),
+                string(hex::encode(serialize(signed))),
+            ),
+            ("inputs".to_string(), Value::Array(inputs)),
+        ]))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use bitcoin::secp256k1::All;
-    use rand::{Rng, SeedableRng};
-    use rand_pcg::Pcg64;
-    use rstest::*;
+    /// Traces the decisions behind the created transaction: why each input is spent, how the
+    /// fee was computed, what became of the change and whether the amounts add up.
+    /// `selection` says how the inputs were chosen
+    pub fn explain(&self, selection: &str) -> Result<Value, TxError> {
+        let transaction = self.created("explain")?;
+        let change_outcome = self
+            .change_outcome
+            .ok_or(TxError::BuilderStateError { method: "explain" })?;
+        let string = |s: String| Value::String(s);
+        let number = |n: u64| Value::Number(n.to_string());
+        let vsize = self.estimated_vsize();
+        let fee = self.calc_fee();
+        // The transaction was created, so the UTXOs cover it
+        let change_amount = self.calc_change_amount().unwrap_or_default();
+        let dust_threshold = self.change_script_pubkey.dust_value().to_sat();
 
-    use super::*;
+        let inputs = self
+            .utxos
+            .iter()
+            .map(|utxo| {
+                Value::Object(vec![
+                    ("txid".to_string(), string(utxo.txid.to_string())),
+                    ("vout".to_string(), number(utxo.vout.into())),
+                    ("amount".to_string(), number(utxo.amount)),
+                    ("reason".to_string(), string(selection.to_string())),
+                    (
+                        "sighash_type".to_string(),
+                        string(utxo.sighash_type.to_string()),
+                    ),
+                ])
+            })
+            .collect();
 
-    #[rstest]
-    #[case(Args {
-        source_address: "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string(),
-        destination_address: "mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string(),
-        private_key: "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP".to_string(),
-        send_amount: 100,
-        utxo_txid: "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331".to_string(),
-        utxo_tx_index: 1,
-        utxo_amount: 4847873,
-        utxo_script_pubkey: "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string(),
-    }, true)]
-    #[case(Args {
-        source_address: "あ".to_string(),
-        destination_address: "mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string(),
-        private_key: "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP".to_string(),
-        send_amount: 100,
-        utxo_txid: "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331".to_string(),
-        utxo_tx_index: 1,
-        utxo_amount: 4847873,
-        utxo_script_pubkey: "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string(),
-    }, false)]
-    #[case(Args {
-        source_address: "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string(),
-        destination_address: "mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string(),
-        private_key: "い".to_string(),
-        send_amount: 100,
-        utxo_txid: "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331".to_string(),
-        utxo_tx_index: 1,
-        utxo_amount: 4847873,
-        utxo_script_pubkey: "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string(),
-    }, false)]
-    #[case(Args {
-        source_address: "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string(),
-        destination_address: "mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string(),
-        private_key: "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP".to_string(),
-        send_amount: 100,
-        utxo_txid: "う".to_string(),
-        utxo_tx_index: 1,
-        utxo_amount: 4847873,
-        utxo_script_pubkey: "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string(),
-    }, false)]
-    #[case(Args {
-        source_address: "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string(),
-        destination_address: "mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string(),
-        private_key: "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP".to_string(),
-        send_amount: 100,
-        utxo_txid: "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331".to_string(),
-        utxo_tx_index: 1,
-        utxo_amount: 4847873,
-        utxo_script_pubkey: "え".to_string(),
-    }, false)]
-    fn test_new(#[case] args: Args, #[case] expected: bool) {
-        assert_eq!(TxBuilder::<All>::new(&args).is_ok(), expected)
-    }
+        let (method, reason) = match (self.fee_rate, self.fee_percent) {
+            _ if self.fixed_fee.is_some() => ("fixed", "given by `--fee`".to_string()),
+            (Some(fee_rate), _) => (
+                "fee_rate",
+                format!("{} sat/vB times the estimated vsize", fee_rate),
+            ),
+            (None, Some(fee_percent)) if self.percent_fee(fee_percent) < fee => (
+                "fee_percent",
+                format!(
+                    "{}% of the payment is below the minimum relay fee, which is used instead",
+                    fee_percent
+                ),
+            ),
+            (None, Some(fee_percent)) => {
+                ("fee_percent", format!("{}% of the payment", fee_percent))
+            }
+            (None, None) => (
+                "flat",
+                "none of `--fee`, `--fee-rate` and `--fee-percent` was given".to_string(),
+            ),
+        };
+        let fee_explanation = Value::Object(vec![
+            ("method".to_string(), string(method.to_string())),
+            ("estimated_vsize".to_string(), number(vsize as u64)),
+            (
+                "effective_fee_rate".to_string(),
+                Value::Number(format!("{:.2}", fee as f64 / vsize as f64)),
+            ),
+            ("amount".to_string(), number(fee)),
+            ("reason".to_string(), string(reason)),
+        ]);
 
-    #[rstest]
-    #[case(10_000, 500, 8_500)]
-    #[case(1_500, 500, 0)]
-    #[should_panic]
-    #[case(10, 100, 0)]
-    fn test_calc_change_amount(
-        #[case] utxo_amount: u64,
-        #[case] send_amount: u64,
-        #[case] expected: u64,
-    ) {
-        let args = Args {
-            source_address: "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string(),
-            destination_address: "mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string(),
-            private_key: "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP".to_string(),
-            send_amount,
-            utxo_txid: "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331"
-                .to_string(),
-            utxo_tx_index: 1,
-            utxo_amount,
-            utxo_script_pubkey: "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string(),
+        let (outcome, reason) = match change_outcome {
+            ChangeOutcome::Created => (
+                "created",
+                format!("not below the dust threshold of {} satoshi", dust_threshold),
+            ),
+            ChangeOutcome::Combined(threshold) => (
+                "combined",
+                format!(
+                    "below the `--combine-dust` threshold of {} satoshi, added to the destination",
+                    threshold
+                ),
+            ),
+            ChangeOutcome::Dropped => (
+                "dropped",
+                format!(
+                    "below the dust threshold of {} satoshi, left to the fee",
+                    dust_threshold
+                ),
+            ),
+            ChangeOutcome::AddedToOutput => (
+                "added_to_output",
+                format!(
+                    "below the dust threshold of {} satoshi, added to the destination",
+                    dust_threshold
+                ),
+            ),
+            ChangeOutcome::Swept => (
+                "swept",
+                "`--sweep` sends everything but the fee to the destination".to_string(),
+            ),
         };
-        let tx_builder = TxBuilder::<All>::new(&args).unwrap();
-        assert_eq!(tx_builder.calc_change_amount(), expected)
+        let change = Value::Object(vec![
+            ("outcome".to_string(), string(outcome.to_string())),
+            ("amount".to_string(), number(change_amount)),
+            ("reason".to_string(), string(reason)),
+        ]);
+
+        // Dropped dust is the only amount that may go to the fee beyond the computed one
+        let dropped = match change_outcome {
+            ChangeOutcome::Dropped => change_amount,
+            _ => 0,
+        };
+        let input_total = self.utxo_amount();
+        let output_total: u64 = transaction.output.iter().map(|output| output.value).sum();
+        let balance = Value::Object(vec![
+            ("input_total".to_string(), number(input_total)),
+            ("output_total".to_string(), number(output_total)),
+            ("fee".to_string(), number(self.fee()?)),
+            (
+                "ok".to_string(),
+                Value::Bool(input_total == output_total + fee + dropped),
+            ),
+        ]);
+
+        Ok(Value::Object(vec![
+            ("inputs".to_string(), Value::Array(inputs)),
+            ("fee".to_string(), fee_explanation),
+            ("change".to_string(), change),
+            ("balance".to_string(), balance),
+        ]))
     }
 
-    #[rstest]
-    // ECDSA Signature: 70-72 bytes
-    // SIGHASH_ALL: 1 byte
-    // Public Key: 33 bytes or 65 bytes
-    // MIN: 70 + 1 + 33 = 104
-    #[case(prepare_test_create_script_sig(1), 104)]
-    #[case(prepare_test_create_script_sig(2), 104)]
-    #[case(prepare_test_create_script_sig(3), 104)]
-    fn test_create_script_sig(
-        #[case] params: (Signature, PublicKey),
-        #[case] expected_min_len: usize,
-    ) {
-        assert!(expected_min_len <= TxBuilder::<All>::create_script_sig(&params.0, &params.1).len())
+    /// Whether the UTXOs cover the payment and the fee
+    pub fn is_funded(&self) -> bool {
+        self.utxo_amount()
+            >= self.send_amount + self.outputs_amount() + self.donation_amount() + self.calc_fee()
+    }
+
+    /// What the UTXOs leave for the change after the payment and the fee, `None` if they fall
+    /// short. Change below the dust threshold is still counted here
+    pub fn funded_change(&self) -> Option<u64> {
+        self.calc_change_amount().ok()
+    }
+
+    /// Network of the (first) private key, testnet if there is none
+    pub fn key_network(&self) -> Network {
+        self.keys
+            .first()
+            .map_or(Network::Testnet, |key| key.network)
+    }
+
+    /// Fails unless every private key belongs to `network`
+    pub fn ensure_key_network(&self, network: Network) -> Result<(), TxError> {
+        for key in self.keys.iter() {
+            if key.network != network {
+                return Err(anyhow!("private key is for {}, not {}", key.network, network).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that every UTXO exists in its previous transaction, for those `lookup` can provide
+    pub fn check_prev_txs(
+        &self,
+        mut lookup: impl FnMut(&Txid) -> Result<Option<Transaction>, anyhow::Error>,
+    ) -> Result<(), anyhow::Error> {
+        for (input_index, utxo) in self.utxos.iter().enumerate() {
+            let Some(prev_tx) = lookup(&utxo.txid)? else {
+                continue;
+            };
+            // Whoever provided it can't swap in a transaction with other amounts
+            ensure!(
+                prev_tx.txid() == utxo.txid,
+                "previous transaction of input {} has txid {}, expected {}",
+                input_index,
+                prev_tx.txid(),
+                utxo.txid
+            );
+            let output = prev_tx.output.get(utxo.vout as usize).ok_or_else(|| {
+                anyhow!(
+                    "vout {} out of range (tx has {} outputs)",
+                    utxo.vout,
+                    prev_tx.output.len()
+                )
+            })?;
+            if output.value != utxo.amount {
+                warning!(
+                    "input {} amount mismatch: using {} satoshi, but the previous transaction \
+                     has {} satoshi",
+                    input_index,
+                    utxo.amount,
+                    output.value
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a PSBT (BIP174) of the unsigned transaction for another signer. SegWit and
+    /// Taproot inputs carry the output they spend; legacy and SegWit v0 inputs also carry the
+    /// previous transaction when `lookup` can provide it, as signers need it to check the amount
+    pub fn psbt(
+        &self,
+        mut lookup: impl FnMut(&Txid) -> Result<Option<Transaction>, anyhow::Error>,
+    ) -> Result<Psbt, anyhow::Error> {
+        let mut psbt = Psbt::from_unsigned_tx(self.created("psbt")?.clone())?;
+
+        for (input_index, (utxo, input)) in self.utxos.iter().zip(&mut psbt.inputs).enumerate() {
+            let spent_output = TxOut {
+                value: utxo.amount,
+                script_pubkey: utxo.script_pubkey.clone(),
+            };
+            if utxo.script_pubkey.is_witness_program() || utxo.redeem_script.is_some() {
+                input.witness_utxo = Some(spent_output);
+            }
+            input.redeem_script = utxo.redeem_script.clone().or_else(|| {
+                utxo.signing_script
+                    .clone()
+                    .filter(|_| utxo.script_pubkey.is_p2sh())
+            });
+            if utxo.script_pubkey.is_v0_p2wsh() {
+                input.witness_script = utxo.signing_script.clone();
+            }
+            if !utxo.script_pubkey.is_v1_p2tr() {
+                input.non_witness_utxo = lookup(&utxo.txid)?;
+                if input.non_witness_utxo.is_none() && !utxo.script_pubkey.is_witness_program() {
+                    warning!(
+                        "the previous transaction of legacy input {} is unknown (see `--prev-tx`), \
+                         which most signers need",
+                        input_index
+                    );
+                }
+            }
+            input.sighash_type = match utxo.sighash_type {
+                SighashType::Ecdsa(EcdsaSighashType::All)
+                | SighashType::Taproot(TapSighashType::Default) => None,
+                SighashType::Ecdsa(sighash_type) => Some(sighash_type.into()),
+                SighashType::Taproot(sighash_type) => Some(sighash_type.into()),
+            };
+        }
+
+        Ok(psbt)
+    }
+
+    /// Estimates the fee and change at each fee rate from the estimated vsize,
+    /// without creating the transaction
+    pub fn fee_table(&self, fee_rates: &[f64]) -> Vec<FeeEstimate> {
+        let dust_threshold = self.change_script_pubkey.dust_value().to_sat();
+
+        fee_rates
+            .iter()
+            .map(|&fee_rate| {
+                let fee = self.fee_at(fee_rate);
+                let change = self.utxo_amount().checked_sub(
+                    self.send_amount + self.outputs_amount() + self.donation_amount() + fee,
+                );
+                FeeEstimate {
+                    fee_rate,
+                    fee,
+                    change,
+                    dust: change.is_some_and(|change| change < dust_threshold),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns why nodes would refuse to relay the created transaction for its fee, if they would
+    pub fn relay_fee_error(&self) -> Option<String> {
+        self.transaction.as_ref()?;
+        // A `--fee` below it is deliberate, e.g. for a zero-fee parent, and only warned about
+        if self.fixed_fee.is_some() {
+            return None;
+        }
+        self.relay_fee_shortfall()
+    }
+
+    fn relay_fee_shortfall(&self) -> Option<String> {
+        let fee = self.fee().ok()?;
+        let min_relay_fee = self.min_relay_fee();
+
+        (fee < min_relay_fee).then(|| {
+            format!(
+                "fee {} below minimum relay fee {} ({} vbytes at {} sat/vB)",
+                fee,
+                min_relay_fee,
+                self.estimated_vsize(),
+                MIN_RELAY_FEE_RATE
+            )
+        })
+    }
+
+    /// Fee the built transaction actually pays, after any dust adjustment
+    pub fn fee(&self) -> Result<u64, TxError> {
+        let transaction = self.created("fee")?;
+        Ok(self.utxo_amount()
+            - transaction
+                .output
+                .iter()
+                .map(|output| output.value)
+                .sum::<u64>())
+    }
+
+    /// Summarizes what the built transaction pays, for a last look before it is used
+    pub fn summary(&self, destination_address: &str) -> Result<String, TxError> {
+        let transaction = self.created("summary")?;
+        let output_amount: u64 = transaction.output.iter().map(|output| output.value).sum();
+        // Change or an `--output` may pay the same script, so the destination is found by index
+        let destination_amount = transaction.output[self.destination_index].value;
+
+        Ok(format!(
+            "destination: {}\namount: {} satoshi\nfee: {} satoshi\nchange: {} satoshi",
+            destination_address,
+            destination_amount,
+            self.fee()?,
+            output_amount - destination_amount - self.outputs_amount() - self.donation_amount()
+        ))
+    }
+
+    /// Change output of the created transaction, if one was made
+    pub fn change(&self) -> Option<u64> {
+        self.calc_change_amount()
+            .ok()
+            .filter(|_| self.change_outcome == Some(ChangeOutcome::Created))
+    }
+
+    pub fn build(&self) -> Result<Tx, TxError> {
+        Ok(Tx(self.created("build")?.clone()))
+    }
+
+    /// The transaction from `create_without_sig`, which `method` can't do without
+    fn created(&self, method: &'static str) -> Result<&Transaction, TxError> {
+        self.transaction
+            .as_ref()
+            .ok_or(TxError::BuilderStateError { method })
+    }
+
+    /// Picks the key whose derived scriptPubKey is the one `utxo` is locked to. A single key is
+    /// used for every input, matching or not
+    fn signing_key(&self, input_index: usize, utxo: &Utxo) -> Result<&PrivateKey, TxError> {
+        let matches = |key: &&PrivateKey| {
+            Self::derive_script_pubkey(&self.secp, &key.public_key(&self.secp), &utxo.script_pubkey)
+                .as_ref()
+                == Some(&utxo.script_pubkey)
+        };
+
+        let error = |reason: &str| TxError::SigningFailed {
+            input_index,
+            reason: reason.to_string(),
+        };
+        if let Some(key) = utxo.key {
+            let key = &self.keys[key];
+            // A custom signing script can lock the input to anything
+            return match utxo.signing_script.is_some() || matches(&key) {
+                true => Ok(key),
+                false => Err(error("its private key does not match its scriptPubKey")),
+            };
+        }
+        match &*self.keys {
+            [] => Err(error("no private key given")),
+            [key] => Ok(key),
+            keys => keys
+                .iter()
+                .find(matches)
+                .ok_or_else(|| error("no private key matches its scriptPubKey")),
+        }
+    }
+
+    /// The outputs spent by the inputs, which Taproot sighashes commit to
+    fn prevouts(&self) -> Vec<TxOut> {
+        self.utxos
+            .iter()
+            .map(|utxo| TxOut {
+                value: utxo.amount,
+                script_pubkey: utxo.script_pubkey.clone(),
+            })
+            .collect()
+    }
+
+    /// Computes the message to sign for `input_index` from the UTXO it spends
+    fn signature_hash(
+        cache: &mut SighashCache<&Transaction>,
+        input_index: usize,
+        utxo: &Utxo,
+        prevouts: &[TxOut],
+    ) -> Result<secp256k1::Message, anyhow::Error> {
+        // Legacy sighashes would silently sign the constant 1 instead
+        if utxo.sighash_type.is_single() && input_index >= cache.transaction().output.len() {
+            return Err(TxError::SighashComputation {
+                input_index,
+                reason: format!("{} needs an output at the same index", utxo.sighash_type),
+            }
+            .into());
+        }
+
+        let sighash_type = match utxo.sighash_type {
+            SighashType::Taproot(sighash_type) => {
+                let sighash = cache.taproot_key_spend_signature_hash(
+                    input_index,
+                    &Prevouts::All(prevouts),
+                    sighash_type,
+                )?;
+                return Ok(secp256k1::Message::from_slice(&sighash[..])?);
+            }
+            SighashType::Ecdsa(sighash_type) => sighash_type,
+        };
+        if let Some(signing_script) = &utxo.signing_script {
+            let error = |e: bitcoin::sighash::Error| TxError::SighashComputation {
+                input_index,
+                reason: e.to_string(),
+            };
+            return Ok(if utxo.script_pubkey.is_v0_p2wsh() {
+                let sighash = cache
+                    .segwit_signature_hash(input_index, signing_script, utxo.amount, sighash_type)
+                    .map_err(error)?;
+                secp256k1::Message::from_slice(&sighash[..])?
+            } else {
+                let sighash = cache
+                    .legacy_signature_hash(input_index, signing_script, sighash_type.to_u32())
+                    .map_err(error)?;
+                secp256k1::Message::from_slice(&sighash[..])?
+            });
+        }
+        // BIP143, which commits to the amount spent
+        let script_code = match &utxo.redeem_script {
+            Some(redeem_script) => redeem_script.p2wpkh_script_code(),
+            None => utxo.script_pubkey.p2wpkh_script_code(),
+        };
+        if let Some(script_code) = script_code {
+            let sighash = cache
+                .segwit_signature_hash(input_index, &script_code, utxo.amount, sighash_type)
+                .map_err(|e| TxError::SighashComputation {
+                    input_index,
+                    reason: e.to_string(),
+                })?;
+            return Ok(secp256k1::Message::from_slice(&sighash[..])?);
+        }
+        ensure!(
+            !utxo.script_pubkey.is_witness_program(),
+            "input {} spends a witness program, which cannot be signed yet",
+            input_index
+        );
+        if utxo.script_pubkey.is_p2sh() {
+            return Err(TxError::SighashComputation {
+                input_index,
+                reason: "P2SH inputs need a P2WPKH redeem script (see `--redeem-script`)"
+                    .to_string(),
+            }
+            .into());
+        }
+
+        // A truncated push makes the output unspendable whatever gets signed
+        if let Some(Err(e)) = utxo.script_pubkey.instructions().find(Result::is_err) {
+            return Err(TxError::SighashComputation {
+                input_index,
+                reason: format!("malformed scriptPubKey ({})", e),
+            }
+            .into());
+        }
+        let sighash = cache
+            .legacy_signature_hash(input_index, &utxo.script_pubkey, sighash_type.to_u32())
+            .map_err(|e| TxError::SighashComputation {
+                input_index,
+                reason: e.to_string(),
+            })?;
+        Ok(secp256k1::Message::from_slice(&sighash[..])?)
+    }
+
+    /// Derives a scriptPubKey spendable by `public_key` with the same type as `template`.
+    /// Returns `None` for types that are not single-key (e.g. P2WSH), and for P2WPKH and
+    /// P2SH-P2WPKH when the key is uncompressed, which SegWit doesn't allow.
+    fn derive_script_pubkey(
+        secp: &Secp256k1<C>,
+        public_key: &PublicKey,
+        template: &ScriptBuf,
+    ) -> Option<ScriptBuf> {
+        if template.is_p2pkh() {
+            Some(ScriptBuf::new_p2pkh(&public_key.pubkey_hash()))
+        } else if template.is_v0_p2wpkh() {
+            Some(ScriptBuf::new_v0_p2wpkh(&public_key.wpubkey_hash()?))
+        } else if template.is_p2sh() {
+            // P2SH-wrapped P2WPKH
+            let redeem_script = ScriptBuf::new_v0_p2wpkh(&public_key.wpubkey_hash()?);
+            Some(ScriptBuf::new_p2sh(&redeem_script.script_hash()))
+        } else if template.is_v1_p2tr() {
+            let (internal_key, _) = public_key.inner.x_only_public_key();
+            Some(ScriptBuf::new_v1_p2tr(secp, internal_key, None))
+        } else {
+            None
+        }
+    }
+
+    fn sequence(&self) -> Sequence {
+        // The locktime is ignored unless at least one input is non-final
+        if self.rbf {
+            Sequence::ENABLE_RBF_NO_LOCKTIME
+        } else if self.lock_time == LockTime::ZERO {
+            Sequence::MAX
+        } else {
+            Sequence::ENABLE_LOCKTIME_NO_RBF
+        }
+    }
+
+    fn input_sequence(&self, utxo: &Utxo) -> Sequence {
+        utxo.sequence.unwrap_or_else(|| self.sequence())
+    }
+
+    /// Refuses `--sequence` values that would silently disable the locktime, BIP68 or BIP125
+    fn check_sequences(&self) -> Result<(), anyhow::Error> {
+        let sequences: Vec<_> = self
+            .utxos
+            .iter()
+            .map(|utxo| self.input_sequence(utxo))
+            .collect();
+
+        for (i, utxo) in self.utxos.iter().enumerate() {
+            if let Some(sequence) = utxo.sequence {
+                ensure!(
+                    !sequence.is_relative_lock_time() || self.version >= 2,
+                    "sequence {:#x} of input {} is a relative timelock (BIP68), which needs \
+                     `--tx-version 2`",
+                    sequence.0,
+                    i
+                );
+            }
+        }
+        ensure!(
+            self.lock_time == LockTime::ZERO
+                || sequences.iter().any(|s| s.enables_absolute_lock_time()),
+            "locktime {} is ignored as every input sequence is {:#x}",
+            self.lock_time,
+            Sequence::MAX.0
+        );
+        ensure!(
+            !self.rbf || sequences.iter().any(|s| s.is_rbf()),
+            "`--rbf` is set, but no input sequence is below {:#x} to signal it",
+            Sequence::ENABLE_LOCKTIME_NO_RBF.0
+        );
+        Ok(())
+    }
+
+    fn calc_change_amount(&self) -> Result<u64, TxError> {
+        let needed =
+            self.send_amount + self.outputs_amount() + self.donation_amount() + self.calc_fee();
+        let available = self.utxo_amount();
+        available
+            .checked_sub(needed)
+            .ok_or(TxError::InsufficientFunds { needed, available })
+    }
+
+    /// Reports a fee above `--max-fee`, or without it above 10% of the input value, which is more
+    /// likely a miscounted UTXO than an intended fee. The flat default fee is always let through
+    fn excessive_fee_error(&self, fee: u64) -> Option<String> {
+        match self.max_fee {
+            Some(max_fee) => (fee > max_fee).then(|| {
+                format!(
+                    "fee of {} satoshi exceeds `--max-fee` of {} satoshi",
+                    fee, max_fee
+                )
+            }),
+            // Small inputs may pay the flat default fee, which was never asked for
+            None if self.default_fee() => None,
+            None => (fee > self.utxo_amount() * MAX_FEE_PERCENT / 100).then(|| {
+                format!(
+                    "fee of {} satoshi is more than {}% of the {} satoshi of inputs",
+                    fee,
+                    MAX_FEE_PERCENT,
+                    self.utxo_amount()
+                )
+            }),
+        }
+    }
+
+    /// Reports a destination value that differs from `--send-amount`, so that no automatic
+    /// adjustment goes unnoticed
+    fn send_amount_warning(&self, destination_value: u64) -> Option<String> {
+        match self.requested_amount {
+            Some(requested) if requested != destination_value => Some(format!(
+                "destination output is {} satoshi instead of the requested {} satoshi ({:+} satoshi)",
+                destination_value,
+                requested,
+                destination_value as i64 - requested as i64
+            )),
+            Some(_) => None,
+            None => Some(format!(
+                "destination output is {} satoshi, everything left after {}the fee",
+                destination_value,
+                if self.sweep { "" } else { "`--reserve` and " }
+            )),
+        }
+    }
+
+    /// Sends all of the inputs but the other outputs and the fee, which is estimated without
+    /// a change output
+    fn sweep_send_amount(&self) -> Result<u64, TxError> {
+        let needed = self.outputs_amount() + self.donation_amount() + self.calc_fee();
+        let available = self.utxo_amount();
+        let send_amount = available
+            .checked_sub(needed)
+            .ok_or(TxError::InsufficientFunds { needed, available })?;
+        let dust_threshold = self.output_script_pubkey.dust_value().to_sat();
+        if send_amount < dust_threshold {
+            return Err(TxError::DustOutput {
+                output: "swept destination output".to_string(),
+                amount: send_amount,
+                threshold: dust_threshold,
+            });
+        }
+        Ok(send_amount)
+    }
+
+    /// Sends what is left after keeping `reserve` as change and paying the fee
+    fn reserve_send_amount(&self, reserve: u64) -> Result<u64, anyhow::Error> {
+        let change_dust_threshold = self.change_script_pubkey.dust_value().to_sat();
+        ensure!(
+            reserve >= change_dust_threshold,
+            "`--reserve` of {} satoshi is below the dust threshold of {} satoshi",
+            reserve,
+            change_dust_threshold
+        );
+
+        let send_amount = self.utxo_amount().saturating_sub(
+            reserve + self.outputs_amount() + self.donation_amount() + self.calc_fee(),
+        );
+        let dust_threshold = self.output_script_pubkey.dust_value().to_sat();
+        ensure!(
+            send_amount >= dust_threshold,
+            "sending {} satoshi after `--reserve` and the fee is below the dust threshold of {} satoshi",
+            send_amount,
+            dust_threshold
+        );
+        Ok(send_amount)
+    }
+
+    pub fn utxo_amount(&self) -> u64 {
+        self.utxos.iter().map(|utxo| utxo.amount).sum()
+    }
+
+    fn outputs_amount(&self) -> u64 {
+        self.outputs.iter().map(|output| output.value).sum()
+    }
+
+    fn donation_amount(&self) -> u64 {
+        self.donation.as_ref().map_or(0, |donation| donation.value)
+    }
+
+    fn calc_fee(&self) -> u64 {
+        if let Some(fee) = self.fixed_fee {
+            return fee;
+        }
+        match (self.fee_rate, self.fee_percent) {
+            (Some(fee_rate), _) => self.fee_at(fee_rate),
+            (None, Some(fee_percent)) => self.percent_fee(fee_percent).max(self.min_relay_fee()),
+            (None, None) => FEE,
+        }
+    }
+
+    /// Whether no fee option was given, so that the fee is the flat `FEE`
+    fn default_fee(&self) -> bool {
+        self.fixed_fee.is_none() && self.fee_rate.is_none() && self.fee_percent.is_none()
+    }
+
+    fn percent_fee(&self, fee_percent: f64) -> u64 {
+        (self.send_amount as f64 * fee_percent / 100.0).ceil() as u64
+    }
+
+    fn min_relay_fee(&self) -> u64 {
+        self.estimated_vsize() as u64 * MIN_RELAY_FEE_RATE
+    }
+
+    fn fee_at(&self, fee_rate: f64) -> u64 {
+        (fee_rate * self.estimated_vsize() as f64).ceil() as u64
+    }
+
+    /// Estimates the virtual size of the signed transaction with both the destination and
+    /// the change output (unless sweeping), assuming 72-byte signatures (including the sighash
+    /// byte). Needs no signing or even `create_without_sig`, so fees can be set from it
+    pub fn estimated_vsize(&self) -> usize {
+        const SIGNATURE_LEN: usize = 72;
+        const SCHNORR_SIGNATURE_LEN: usize = 64;
+
+        // Signers of a PSBT are assumed to use compressed keys
+        let pubkey_len = self
+            .public_key
+            .map_or(33, |public_key| public_key.to_bytes().len());
+        let mut outputs = vec![&self.output_script_pubkey];
+        if !self.sweep {
+            outputs.push(&self.change_script_pubkey);
+        }
+        outputs.extend(self.outputs.iter().map(|output| &output.script_pubkey));
+        outputs.extend(
+            [&self.donation, &self.op_return]
+                .into_iter()
+                .flatten()
+                .map(|output| &output.script_pubkey),
+        );
+
+        // Version, locktime and counts
+        let mut base_size = 4 + 4 + varint_len(self.utxos.len()) + varint_len(outputs.len());
+        let mut witness_size = 0;
+        let mut legacy_inputs = 0;
+
+        for utxo in &self.utxos {
+            // Outpoint and sequence
+            base_size += 36 + 4;
+
+            let script_pubkey = &utxo.script_pubkey;
+            if script_pubkey.is_v0_p2wpkh() {
+                base_size += 1;
+                witness_size += 1 + (1 + SIGNATURE_LEN) + (1 + pubkey_len);
+            } else if script_pubkey.is_v1_p2tr() {
+                // Non-default sighash types append their byte
+                let sighash_len = match utxo.sighash_type {
+                    SighashType::Taproot(TapSighashType::Default) => 0,
+                    _ => 1,
+                };
+                base_size += 1;
+                witness_size += 1 + (1 + SCHNORR_SIGNATURE_LEN + sighash_len);
+            } else if let Some(signing_script) = &utxo.signing_script {
+                let script_len = signing_script.len();
+                if script_pubkey.is_v0_p2wsh() {
+                    base_size += 1;
+                    witness_size += 1
+                        + (1 + SIGNATURE_LEN)
+                        + (1 + pubkey_len)
+                        + varint_len(script_len)
+                        + script_len;
+                } else {
+                    // A P2SH script_sig also pushes the redeem script, with OP_PUSHDATA beyond 75
+                    let push_len = match (script_pubkey.is_p2sh(), script_len) {
+                        (false, _) => 0,
+                        (true, 0..=75) => 1 + script_len,
+                        (true, 76..=0xff) => 2 + script_len,
+                        (true, _) => 3 + script_len,
+                    };
+                    let script_sig_len = (1 + SIGNATURE_LEN) + (1 + pubkey_len) + push_len;
+                    base_size += varint_len(script_sig_len) + script_sig_len;
+                    legacy_inputs += 1;
+                }
+            } else if script_pubkey.is_p2sh() {
+                // Assumes P2SH-P2WPKH: the script_sig pushes the 22-byte redeem script
+                base_size += 1 + 23;
+                witness_size += 1 + (1 + SIGNATURE_LEN) + (1 + pubkey_len);
+            } else {
+                base_size += 1 + (1 + SIGNATURE_LEN) + (1 + pubkey_len);
+                legacy_inputs += 1;
+            }
+        }
+
+        for script_pubkey in outputs {
+            base_size += 8 + varint_len(script_pubkey.len()) + script_pubkey.len();
+        }
+
+        // Marker, flag and an empty witness for each legacy input
+        if witness_size > 0 {
+            witness_size += 2 + legacy_inputs;
+        }
+
+        (base_size * 4 + witness_size).div_ceil(4)
+    }
+
+    /// Pushes the redeem script of a P2SH-P2WPKH input, whose signature goes in the witness
+    fn p2sh_script_sig(redeem_script: &Script) -> ScriptBuf {
+        let mut script_sig = vec![redeem_script.len() as u8];
+        script_sig.extend_from_slice(redeem_script.as_bytes());
+        ScriptBuf::from(script_sig)
+    }
+
+    fn create_script_sig(
+        signature: &Signature,
+        public_key: &PublicKey,
+        sighash_type: EcdsaSighashType,
+    ) -> Vec<u8> {
+        let mut script_sig = Vec::new();
+
+        let serialized_sig = signature.serialize_der();
+        let sighash_byte = sighash_type.to_u32() as u8;
+        // One push of the DER signature followed by the sighash byte, whatever its value
+        script_sig.push(serialized_sig.len() as u8 + 1);
+        script_sig.extend_from_slice(&serialized_sig);
+        script_sig.push(sighash_byte);
+
+        let serialized_pubkey = public_key.to_bytes();
+        script_sig.push(serialized_pubkey.len() as u8);
+        script_sig.extend_from_slice(&serialized_pubkey);
+
+        script_sig
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::secp256k1::All;
+    use rand::{Rng, SeedableRng};
+    use rand_pcg::Pcg64;
+    use rstest::*;
+
+    use super::*;
+    use crate::{
+        args::{Network as ArgsNetwork, Payment},
+        log::{self, Level},
+    };
+
+    #[rstest]
+    #[case(Args {
+        source_address: "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string(),
+        destination_address: Some("mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string()),
+        private_key: Some("cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP".into()),
+        send_amount: Some(100),
+        utxo_txid: Some("d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331".to_string()),
+        utxo_tx_index: Some(1),
+        utxo_amount: Some(4847873),
+        utxo_script_pubkey: Some("76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string()),
+        ..Default::default()
+    }, true)]
+    #[case(Args {
+        source_address: "あ".to_string(),
+        destination_address: Some("mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string()),
+        private_key: Some("cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP".into()),
+        send_amount: Some(100),
+        utxo_txid: Some("d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331".to_string()),
+        utxo_tx_index: Some(1),
+        utxo_amount: Some(4847873),
+        utxo_script_pubkey: Some("76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string()),
+        ..Default::default()
+    }, false)]
+    #[case(Args {
+        source_address: "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string(),
+        destination_address: Some("mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string()),
+        private_key: Some("い".into()),
+        send_amount: Some(100),
+        utxo_txid: Some("d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331".to_string()),
+        utxo_tx_index: Some(1),
+        utxo_amount: Some(4847873),
+        utxo_script_pubkey: Some("76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string()),
+        ..Default::default()
+    }, false)]
+    #[case(Args {
+        source_address: "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string(),
+        destination_address: Some("mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string()),
+        private_key: Some("cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP".into()),
+        send_amount: Some(100),
+        utxo_txid: Some("う".to_string()),
+        utxo_tx_index: Some(1),
+        utxo_amount: Some(4847873),
+        utxo_script_pubkey: Some("76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string()),
+        ..Default::default()
+    }, false)]
+    #[case(Args {
+        source_address: "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string(),
+        destination_address: Some("mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string()),
+        private_key: Some("cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP".into()),
+        send_amount: Some(100),
+        utxo_txid: Some("d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331".to_string()),
+        utxo_tx_index: Some(1),
+        utxo_amount: Some(4847873),
+        utxo_script_pubkey: Some("え".to_string()),
+        ..Default::default()
+    }, false)]
+    fn test_new(#[case] args: Args, #[case] expected: bool) {
+        assert_eq!(TxBuilder::<All>::new(&args).is_ok(), expected)
+    }
+
+    #[rstest]
+    #[case(10_000, 500, Ok(8_500))]
+    #[case(1_500, 500, Ok(0))]
+    // 100 satoshi plus the fee of 1000 satoshi, from 10 satoshi
+    #[case(10, 100, Err((1_100, 10)))]
+    fn test_calc_change_amount(
+        #[case] utxo_amount: u64,
+        #[case] send_amount: u64,
+        #[case] expected: Result<u64, (u64, u64)>,
+    ) {
+        let args = Args {
+            source_address: "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string(),
+            destination_address: Some("mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string()),
+            private_key: Some("cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP".into()),
+            send_amount: Some(send_amount),
+            utxo_txid: Some(
+                "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331".to_string(),
+            ),
+            utxo_tx_index: Some(1),
+            utxo_amount: Some(utxo_amount),
+            utxo_script_pubkey: Some(
+                "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string(),
+            ),
+            ..Default::default()
+        };
+        let tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let result = tx_builder.calc_change_amount().map_err(|e| match e {
+            TxError::InsufficientFunds { needed, available } => (needed, available),
+            e => panic!("unexpected error: {}", e),
+        });
+        assert_eq!(result, expected)
+    }
+
+    #[rstest]
+    fn test_builder_state_error() {
+        let mut tx_builder = TxBuilder::<All>::new(&sample_args()).unwrap();
+
+        assert!(matches!(
+            tx_builder.sign().map(|_| ()).unwrap_err(),
+            TxError::BuilderStateError { method: "sign" }
+        ));
+        assert_eq!(
+            tx_builder.build().err().unwrap().to_string(),
+            "`build` needs the transaction from `create_without_sig` first"
+        );
+        assert!(matches!(
+            tx_builder.explain("given"),
+            Err(TxError::BuilderStateError { method: "explain" })
+        ));
+        assert!(matches!(
+            tx_builder.fee(),
+            Err(TxError::BuilderStateError { method: "fee" })
+        ));
+        assert!(matches!(
+            tx_builder.summary("mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn"),
+            Err(TxError::BuilderStateError { method: "summary" })
+        ));
+        assert!(matches!(
+            tx_builder.verification_bundle(),
+            Err(TxError::BuilderStateError {
+                method: "verification_bundle"
+            })
+        ));
+        tx_builder.create_without_sig().unwrap();
+        assert_eq!(
+            tx_builder.verification_bundle().unwrap_err().to_string(),
+            "`verification_bundle` needs the transaction signed by `sign` first"
+        );
+    }
+
+    #[rstest]
+    fn test_create_insufficient_funds() {
+        let args = Args {
+            utxo_amount: Some(1_050),
+            ..sample_args()
+        };
+        let error = TxBuilder::<All>::new(&args)
+            .unwrap()
+            .create_without_sig()
+            .map(|_| ())
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "insufficient funds: 1100 satoshi needed, 1050 satoshi available (50 satoshi short)"
+        );
+    }
+
+    #[rstest]
+    // P2PKH
+    #[case(
+        "mqwpxxvfv3QbM8PU8uBx2jaNt9btQqvQNx",
+        ChangeMatches::Destination,
+        "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac"
+    )]
+    // P2WPKH
+    #[case(
+        "tb1qwfjcnutuv4djp2qr73vejvvs0gzs6pu9gypkwh",
+        ChangeMatches::Destination,
+        "00143d927250d4a4744f5f99b499f750d85054dbf9fc"
+    )]
+    #[case(
+        "tb1qwfjcnutuv4djp2qr73vejvvs0gzs6pu9gypkwh",
+        ChangeMatches::Source,
+        "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac"
+    )]
+    // P2SH (P2SH-P2WPKH)
+    #[case(
+        "2NCqUYLbZTWg7wUeXJyi6px9eQaAyBouyv1",
+        ChangeMatches::Destination,
+        "a9140b18ea6f1de6ef6513e23971bab139a5c09170bc87"
+    )]
+    // P2TR
+    #[case(
+        "tb1pwmydyhhlqfv6zzg90m2pg0248hcu9vgqz7rturcvhspjpaqewjpqxp56zn",
+        ChangeMatches::Destination,
+        "51200ed826d227154db596a48274ee173330fcafcc146bd1bcba34e6befe7aa438ba"
+    )]
+    // P2WSH can't be derived from a single key, so change goes back to the source
+    #[case(
+        "tb1qft5p2uhsdcdc3l2ua4ap5qqfg4pjaqlp250x7us7a8qqhrxrxfsqaqh7jw",
+        ChangeMatches::Destination,
+        "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac"
+    )]
+    fn test_change_matches(
+        #[case] destination_address: &str,
+        #[case] change_matches: ChangeMatches,
+        #[case] expected: &str,
+    ) {
+        let args = Args {
+            destination_address: Some(destination_address.to_string()),
+            change_matches,
+            ..sample_args()
+        };
+        let tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        assert_eq!(tx_builder.change_script_pubkey.to_hex_string(), expected)
+    }
+
+    #[rstest]
+    // P2WPKH and P2SH-P2WPKH can't be derived from an uncompressed key
+    #[case("tb1qwfjcnutuv4djp2qr73vejvvs0gzs6pu9gypkwh")]
+    #[case("2MtFuGa67i3Rsvo6YAffgqHeEjuArDWdZJT")]
+    // P2PKH can
+    #[case("mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn")]
+    fn test_change_matches_uncompressed(#[case] destination_address: &str) {
+        let args = Args {
+            source_address: "n1S9T9p9WJss8d1eQsheokZjGfAQyEtchg".to_string(),
+            destination_address: Some(destination_address.to_string()),
+            utxo_script_pubkey: Some(
+                "76a914da79bfc8bc2845ff4c4ab2f846dbe8bdd3e4162288ac".to_string(),
+            ),
+            uncompressed: true,
+            ..sample_args()
+        };
+        let tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        assert_eq!(
+            tx_builder.change_script_pubkey.to_hex_string(),
+            "76a914da79bfc8bc2845ff4c4ab2f846dbe8bdd3e4162288ac"
+        )
+    }
+
+    #[rstest]
+    #[case(None, None, false, Ok(()))]
+    #[case(Some(20_000), None, false, Ok(()))]
+    #[case(
+        Some(1_000_000),
+        None,
+        false,
+        Err("fee of 1000000 satoshi is more than 10% of the 4847873 satoshi of inputs (use `--force` to build it anyway)")
+    )]
+    #[case(Some(1_000_000), None, true, Ok(()))]
+    #[case(Some(4_000), Some(4_000), false, Ok(()))]
+    #[case(
+        Some(4_001),
+        Some(4_000),
+        false,
+        Err("fee of 4001 satoshi exceeds `--max-fee` of 4000 satoshi (use `--force` to build it anyway)")
+    )]
+    fn test_excessive_fee(
+        #[case] fee: Option<u64>,
+        #[case] max_fee: Option<u64>,
+        #[case] force: bool,
+        #[case] expected: Result<(), &str>,
+    ) {
+        let args = Args {
+            fee,
+            max_fee,
+            force,
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let result = tx_builder.create_without_sig().map(|_| ());
+
+        assert_eq!(
+            result.map_err(|e| e.to_string()),
+            expected.map_err(str::to_string)
+        )
+    }
+
+    #[rstest]
+    // The default fee is exempt however small the input
+    #[case(None, Ok(()))]
+    #[case(
+        Some(800),
+        Err("fee of 800 satoshi is more than 10% of the 5000 satoshi of inputs (use `--force` to build it anyway)")
+    )]
+    fn test_excessive_small_fee(#[case] fee: Option<u64>, #[case] expected: Result<(), &str>) {
+        let args = Args {
+            fee,
+            utxo_amount: Some(5_000),
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let result = tx_builder.create_without_sig().map(|_| ());
+
+        assert_eq!(
+            result.map_err(|e| e.to_string()),
+            expected.map_err(str::to_string)
+        )
+    }
+
+    #[rstest]
+    fn test_sign_p2sh_p2wpkh() {
+        // The P2SH-P2WPKH example of BIP143
+        let address = |hash: &str| {
+            let script_pubkey = ScriptBuf::new_p2pkh(&bitcoin::PubkeyHash::from_str(hash).unwrap());
+            Address::from_script(&script_pubkey, Network::Bitcoin)
+                .unwrap()
+                .to_string()
+        };
+        let args = Args {
+            source_address: address("79091972186c449eb1ded22b78e40d009bdf0089"),
+            destination_address: Some(address("a457b684d7f0d539a46a45bbc043f35b59d0d963")),
+            outputs: vec![Payment {
+                address: address("fd270b1ee6abcaea97fea7ad0402e8bd8ad6d77c"),
+                amount: 800_000_000,
+            }],
+            private_key: Some(
+                "eb696a065ef48a2192da5b28b694f87544b30fae8327c4510137a922f32c6dcf".into(),
+            ),
+            network: Some(ArgsNetwork::Mainnet),
+            send_amount: Some(199_996_600),
+            fee: Some(3_400),
+            lock_time: 1170,
+            utxo_txid: Some(
+                "77541aeb3c4dac9260b68f74f44c973081a9d4cb2ebe8038b2d70faa201b6bdb".to_string(),
+            ),
+            utxo_tx_index: Some(1),
+            utxo_amount: Some(1_000_000_000),
+            utxo_script_pubkey: Some("a9144733f37cf4db86fbc2efed2500b4f4e49f31202387".to_string()),
+            ..Default::default()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        tx_builder.create_without_sig().unwrap();
+        let tx = tx_builder
+            .sign()
+            .unwrap()
+            .verify()
+            .unwrap()
+            .build()
+            .unwrap();
+        let input = &tx.0.input[0];
+
+        // The script_sig pushes the redeem script, the witness holds the signature and the key
+        assert_eq!(
+            input.script_sig.to_hex_string(),
+            "16001479091972186c449eb1ded22b78e40d009bdf0089"
+        );
+        assert_eq!(input.witness.len(), 2);
+        assert_eq!(tx.hex(), "01000000000101db6b1b20aa0fd7b23880be2ecbd4a98130974cf4748fb66092ac4d3ceb1a5477010000001716001479091972186c449eb1ded22b78e40d009bdf0089feffffff02b8b4eb0b000000001976a914a457b684d7f0d539a46a45bbc043f35b59d0d96388ac0008af2f000000001976a914fd270b1ee6abcaea97fea7ad0402e8bd8ad6d77c88ac02473044022047ac8e878352d3ebbde1c94ce3a10d057c24175747116f8288e5d794d12d482f0220217f36a485cae903c713331d877c1f64677e3622ad4010726870540656fe9dcb012103ad1d8e89212f0b92c74d23bb710c00662ad1470198ac48c43f7d6f93a2a2687392040000")
+    }
+
+    #[rstest]
+    // Given, or derived from the key
+    #[case(Some("00143d927250d4a4744f5f99b499f750d85054dbf9fc"), Ok(()))]
+    #[case(None, Ok(()))]
+    #[case(
+        Some("0014a997f6d478624028ea1f36082e7ceb5d79d75671"),
+        Err("`--redeem-script` does not match the scriptPubKey of input 0")
+    )]
+    fn test_redeem_script(#[case] redeem_script: Option<&str>, #[case] expected: Result<(), &str>) {
+        // P2SH of the P2WPKH redeem script of cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP
+        let p2wpkh = ScriptBuf::from_hex("00143d927250d4a4744f5f99b499f750d85054dbf9fc").unwrap();
+        let args = Args {
+            utxo_script_pubkey: Some(ScriptBuf::new_p2sh(&p2wpkh.script_hash()).to_hex_string()),
+            redeem_script: redeem_script.map(str::to_string),
+            ..sample_args()
+        };
+        let result = TxBuilder::<All>::new(&args).and_then(|mut tx_builder| {
+            tx_builder.create_without_sig()?.sign()?.verify()?;
+            Ok(())
+        });
+
+        assert_eq!(
+            result.map_err(|e| e.to_string()),
+            expected.map_err(str::to_string)
+        )
+    }
+
+    #[rstest]
+    #[case("a9142e637a1cab6995311ce4107b02695a7dfad8876387")]
+    #[case("0020d30b0075c8b81be88f40ca1a085fe6ee2977f8bb0097b3cdef161bdea395d931")]
+    fn test_signing_script(#[case] script_pubkey: &str) {
+        let signing_script = "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac";
+        let args = Args {
+            utxo_script_pubkey: Some(script_pubkey.to_string()),
+            signing_script: Some(signing_script.to_string()),
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        tx_builder
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .verify()
+            .unwrap();
+        let tx = tx_builder.build().unwrap();
+
+        let script_pubkey = ScriptBuf::from_hex(script_pubkey).unwrap();
+        let signing_script = ScriptBuf::from_hex(signing_script).unwrap();
+        let input = &tx.0.input[0];
+        let pushes: Vec<Vec<u8>> = if script_pubkey.is_v0_p2wsh() {
+            assert!(input.script_sig.is_empty());
+            input.witness.iter().map(<[u8]>::to_vec).collect()
+        } else {
+            input
+                .script_sig
+                .instructions()
+                .map(|instruction| match instruction.unwrap() {
+                    Instruction::PushBytes(bytes) => bytes.as_bytes().to_vec(),
+                    Instruction::Op(op) => panic!("unexpected {:?}", op),
+                })
+                .collect()
+        };
+        assert_eq!(pushes.len(), 3);
+        assert_eq!(pushes[2], signing_script.as_bytes());
+
+        let sighash = |script: &Script| {
+            let mut cache = SighashCache::new(&tx.0);
+            let sighash = if script_pubkey.is_v0_p2wsh() {
+                cache
+                    .segwit_signature_hash(0, script, 4847873, EcdsaSighashType::All)
+                    .unwrap()[..]
+                    .to_vec()
+            } else {
+                cache
+                    .legacy_signature_hash(0, script, EcdsaSighashType::All.to_u32())
+                    .unwrap()[..]
+                    .to_vec()
+            };
+            secp256k1::Message::from_slice(&sighash).unwrap()
+        };
+        let (der, _) = pushes[0].split_at(pushes[0].len() - 1);
+        let signature = Signature::from_der(der).unwrap();
+        let public_key = PublicKey::from_slice(&pushes[1]).unwrap();
+        let verifies = |script: &Script| {
+            tx_builder
+                .secp
+                .verify_ecdsa(&sighash(script), &signature, &public_key.inner)
+                .is_ok()
+        };
+        // Signed against the signing script, not the scriptPubKey it hashes to
+        assert!(verifies(&signing_script));
+        assert!(!verifies(&script_pubkey));
+        let estimated = tx_builder.estimated_vsize();
+        assert!((tx.0.vsize()..=tx.0.vsize() + 1).contains(&estimated));
+    }
+
+    #[rstest]
+    #[case(
+        "a9142e637a1cab6995311ce4107b02695a7dfad8876487",
+        "`--signing-script` does not match the scriptPubKey of input 0"
+    )]
+    #[case(
+        "00143d927250d4a4744f5f99b499f750d85054dbf9fc",
+        "`--signing-script` only applies to legacy, P2SH and P2WSH inputs, not input 0"
+    )]
+    fn test_signing_script_error(#[case] script_pubkey: &str, #[case] expected: &str) {
+        let args = Args {
+            utxo_script_pubkey: Some(script_pubkey.to_string()),
+            signing_script: Some("76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string()),
+            ..sample_args()
+        };
+        let error = TxBuilder::<All>::new(&args).err().unwrap();
+        assert_eq!(error.to_string(), expected);
+    }
+
+    #[rstest]
+    #[case(ChangeMatches::Source)]
+    #[case(ChangeMatches::Destination)]
+    fn test_change_address(#[case] change_matches: ChangeMatches) {
+        let args = Args {
+            change_address: Some("mqwpxxvfv3QbM8PU8uBx2jaNt9btQqvQNx".to_string()),
+            change_matches,
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        tx_builder.create_without_sig().unwrap();
+        let tx = tx_builder.sign().unwrap().build().unwrap();
+
+        assert_eq!(
+            tx.0.output[1].script_pubkey,
+            address_script_pubkey("mqwpxxvfv3QbM8PU8uBx2jaNt9btQqvQNx", Network::Testnet).unwrap()
+        )
+    }
+
+    #[rstest]
+    #[case(None, vec![9_000, 10_000])]
+    #[case(Some("mqwpxxvfv3QbM8PU8uBx2jaNt9btQqvQNx:2500"), vec![9_000, 2_500, 7_500])]
+    fn test_donate(#[case] donate: Option<&str>, #[case] expected: Vec<u64>) {
+        let args = Args {
+            send_amount: Some(9_000),
+            utxo_amount: Some(20_000),
+            donate: donate.map(|d| d.parse().unwrap()),
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let transaction = tx_builder
+            .create_without_sig()
+            .unwrap()
+            .transaction
+            .as_ref()
+            .unwrap();
+        let output_values: Vec<u64> = transaction.output.iter().map(|o| o.value).collect();
+
+        assert_eq!(output_values, expected);
+        if donate.is_some() {
+            assert_eq!(
+                transaction.output[1].script_pubkey.to_hex_string(),
+                "76a914726589f17c655b20a803f4599931907a050d078588ac"
+            );
+        }
+    }
+
+    #[rstest]
+    #[case(9_000, 20_000, false)]
+    // Destination of 0 satoshi and dust change dropped: only the OP_RETURN output has a purpose
+    #[case(0, 1_500, true)]
+    fn test_spendable_output_error(
+        #[case] send_amount: u64,
+        #[case] utxo_amount: u64,
+        #[case] expected: bool,
+    ) {
+        let args = Args {
+            send_amount: Some(send_amount),
+            utxo_amount: Some(utxo_amount),
+            op_return: Some("68656c6c6f".to_string()),
+            ..sample_args()
+        };
+        let tx = TxBuilder::<All>::new(&args)
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert!(tx
+            .0
+            .output
+            .iter()
+            .any(|o| o.script_pubkey.to_hex_string() == "6a0568656c6c6f"));
+        assert_eq!(tx.spendable_output_error().is_some(), expected)
+    }
+
+    #[rstest]
+    // P2PKH outputs are 34 bytes, so the threshold is 546 satoshi
+    #[case("mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn", 546, None)]
+    #[case(
+        "mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn",
+        545,
+        Some("output 0 to mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn is 545 satoshi, below the dust threshold of 546 satoshi")
+    )]
+    // P2WPKH outputs are 31 bytes and discounted as witness spends, so the threshold is 294 satoshi
+    #[case("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx", 294, None)]
+    #[case(
+        "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+        293,
+        Some("output 0 to tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx is 293 satoshi, below the dust threshold of 294 satoshi")
+    )]
+    fn test_dust_output_error(
+        #[case] destination_address: &str,
+        #[case] send_amount: u64,
+        #[case] expected: Option<&str>,
+    ) {
+        let args = Args {
+            destination_address: Some(destination_address.to_string()),
+            send_amount: Some(send_amount),
+            op_return: Some("68656c6c6f".to_string()),
+            ..sample_args()
+        };
+        let tx = TxBuilder::<All>::new(&args)
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .build()
+            .unwrap();
+
+        // The OP_RETURN output of 0 satoshi is never dust
+        assert_eq!(tx.dust_output_error(Network::Testnet).as_deref(), expected)
+    }
+
+    #[rstest]
+    #[case(OutputOrder::AsGiven, vec![(9_000, "dest"), (2_500, "donation"), (7_500, "change")])]
+    #[case(OutputOrder::ChangeLast, vec![(9_000, "dest"), (2_500, "donation"), (7_500, "change")])]
+    #[case(OutputOrder::ChangeFirst, vec![(7_500, "change"), (9_000, "dest"), (2_500, "donation")])]
+    #[case(OutputOrder::Bip69, vec![(2_500, "donation"), (7_500, "change"), (9_000, "dest")])]
+    fn test_output_order(#[case] output_order: OutputOrder, #[case] expected: Vec<(u64, &str)>) {
+        let args = Args {
+            send_amount: Some(9_000),
+            utxo_amount: Some(20_000),
+            donate: Some("mqwpxxvfv3QbM8PU8uBx2jaNt9btQqvQNx:2500".parse().unwrap()),
+            output_order,
+            ..sample_args()
+        };
+        let tx = TxBuilder::<All>::new(&args)
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap();
+        let name = |script_pubkey: &ScriptBuf| match script_pubkey.to_hex_string().as_str() {
+            "76a914a997f6d478624028ea1f36082e7ceb5d79d7567188ac" => "dest",
+            "76a914726589f17c655b20a803f4599931907a050d078588ac" => "donation",
+            "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac" => "change",
+            _ => unreachable!(),
+        };
+        let outputs: Vec<(u64, &str)> =
+            tx.0.output
+                .iter()
+                .map(|output| (output.value, name(&output.script_pubkey)))
+                .collect();
+
+        assert_eq!(outputs, expected)
+    }
+
+    #[rstest]
+    #[case(
+        OutputOrder::Bip69,
+        vec![
+            (1_000, "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac"),
+            (1_000, "76a914726589f17c655b20a803f4599931907a050d078588ac"),
+            (8_000, "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac"),
+            (9_000, "76a914a997f6d478624028ea1f36082e7ceb5d79d7567188ac"),
+        ]
+    )]
+    #[case(OutputOrder::Random, Vec::new())]
+    fn test_output_order_outputs(
+        #[case] output_order: OutputOrder,
+        #[case] expected: Vec<(u64, &str)>,
+    ) {
+        let args = Args {
+            send_amount: Some(9_000),
+            utxo_amount: Some(20_000),
+            outputs: vec![
+                "mqwpxxvfv3QbM8PU8uBx2jaNt9btQqvQNx:1000".parse().unwrap(),
+                "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx:1000".parse().unwrap(),
+            ],
+            output_order,
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        tx_builder.create_without_sig().unwrap().sign().unwrap();
+        // Signed over the reordered outputs
+        tx_builder.verify().unwrap();
+        let mut outputs: Vec<(u64, String)> = tx_builder
+            .build()
+            .unwrap()
+            .0
+            .output
+            .iter()
+            .map(|output| (output.value, output.script_pubkey.to_hex_string()))
+            .collect();
+
+        match output_order {
+            OutputOrder::Random => {
+                outputs.sort();
+                assert_eq!(outputs.len(), 4);
+                assert_eq!(outputs[3].0, 9_000);
+            }
+            _ => assert_eq!(
+                outputs,
+                expected
+                    .into_iter()
+                    .map(|(value, script_pubkey)| (value, script_pubkey.to_string()))
+                    .collect::<Vec<_>>()
+            ),
+        }
+    }
+
+    #[rstest]
+    #[case(OutputOrder::Random, 0, 20_000, Ok(()))]
+    #[case(OutputOrder::Bip69, 0, 20_000, Ok(()))]
+    #[case(OutputOrder::ChangeFirst, 3, 20_000, Ok(()))]
+    #[case(
+        OutputOrder::AsGiven,
+        4,
+        20_000,
+        Err("`--change-index` 4 is out of range for 4 outputs")
+    )]
+    // 200 satoshi of change is dust, so there is none to place
+    #[case(
+        OutputOrder::AsGiven,
+        0,
+        12_200,
+        Err("`--change-index` 0 is given, but the transaction has no change output")
+    )]
+    fn test_change_index(
+        #[case] output_order: OutputOrder,
+        #[case] change_index: usize,
+        #[case] utxo_amount: u64,
+        #[case] expected: Result<(), &str>,
+    ) {
+        let args = Args {
+            send_amount: Some(9_000),
+            utxo_amount: Some(utxo_amount),
+            outputs: vec![
+                "mqwpxxvfv3QbM8PU8uBx2jaNt9btQqvQNx:1000".parse().unwrap(),
+                "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx:1000".parse().unwrap(),
+            ],
+            output_order,
+            change_index: Some(change_index),
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let result = tx_builder
+            .create_without_sig()
+            .and_then(|tx_builder| tx_builder.sign())
+            .map(|_| ());
+        if let Err(expected) = expected {
+            assert_eq!(result.unwrap_err().to_string(), expected);
+            return;
+        }
+        // Signed over the final order
+        tx_builder.verify().unwrap();
+        let tx = tx_builder.build().unwrap();
+
+        assert_eq!(tx.0.output.len(), 4);
+        assert_eq!(tx.0.output[change_index].value, 8_000);
+        assert_eq!(
+            tx.0.output[change_index].script_pubkey.to_hex_string(),
+            "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac"
+        );
+    }
+
+    #[rstest]
+    fn test_destination_script() {
+        // Bare 1-of-1 multisig, which has no address
+        let script = "51210303998660a6a026b2f8aa72d37a077b6a76b282b2d5b73fc582fdc274f66fa5bc51ae";
+        let args = Args {
+            destination_address: None,
+            destination_script: Some(script.to_string()),
+            send_amount: Some(10_000),
+            ..sample_args()
+        };
+        let tx = TxBuilder::<All>::new(&args)
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(tx.0.output[0].script_pubkey.to_hex_string(), script);
+        assert_eq!(tx.0.output[0].value, 10_000);
+    }
+
+    #[rstest]
+    fn test_outputs() {
+        let args = Args {
+            send_amount: Some(9_000),
+            utxo_amount: Some(20_000),
+            outputs: vec![
+                "mqwpxxvfv3QbM8PU8uBx2jaNt9btQqvQNx:1000".parse().unwrap(),
+                "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx:2000".parse().unwrap(),
+                "mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn:3000".parse().unwrap(),
+            ],
+            ..sample_args()
+        };
+        let tx = TxBuilder::<All>::new(&args)
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .build()
+            .unwrap();
+        let outputs: Vec<(u64, String)> =
+            tx.0.output
+                .iter()
+                .map(|output| (output.value, output.script_pubkey.to_hex_string()))
+                .collect();
+
+        assert_eq!(
+            outputs,
+            vec![
+                (
+                    9_000,
+                    "76a914a997f6d478624028ea1f36082e7ceb5d79d7567188ac".to_string()
+                ),
+                (
+                    1_000,
+                    "76a914726589f17c655b20a803f4599931907a050d078588ac".to_string()
+                ),
+                (
+                    2_000,
+                    "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string()
+                ),
+                (
+                    3_000,
+                    "76a914a997f6d478624028ea1f36082e7ceb5d79d7567188ac".to_string()
+                ),
+                // 20_000 - 15_000 - 1_000 fee
+                (
+                    4_000,
+                    "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string()
+                ),
+            ]
+        )
+    }
+
+    #[rstest]
+    #[case(20_000, 10_000, Some(vec![9_000, 10_000]))]
+    #[case(10_000, 600, Some(vec![8_400, 600]))]
+    // Reserve below the dust threshold of 546 satoshi
+    #[case(10_000, 500, None)]
+    // Nothing left to send
+    #[case(10_000, 9_000, None)]
+    fn test_reserve(
+        #[case] utxo_amount: u64,
+        #[case] reserve: u64,
+        #[case] expected: Option<Vec<u64>>,
+    ) {
+        let args = Args {
+            send_amount: None,
+            reserve: Some(reserve),
+            utxo_amount: Some(utxo_amount),
+            ..sample_args()
+        };
+        let result = TxBuilder::<All>::new(&args);
+
+        match expected {
+            Some(expected) => {
+                let tx = result
+                    .unwrap()
+                    .create_without_sig()
+                    .unwrap()
+                    .build()
+                    .unwrap();
+                let output_values: Vec<u64> = tx.0.output.iter().map(|o| o.value).collect();
+                assert_eq!(output_values, expected)
+            }
+            None => assert!(result.is_err()),
+        }
+    }
+
+    #[rstest]
+    #[case(None, DustPolicy::Fail, Some(4_846_873))]
+    // 1-in 1-out P2PKH, 192 vbytes
+    #[case(Some(2.0), DustPolicy::Fail, Some(4_847_489))]
+    #[case(None, DustPolicy::AddToOutput, Some(4_846_873))]
+    // Nothing left after the fee
+    #[case(Some(30_000.0), DustPolicy::Drop, None)]
+    fn test_sweep(
+        #[case] fee_rate: Option<f64>,
+        #[case] dust_policy: DustPolicy,
+        #[case] expected: Option<u64>,
+    ) {
+        let args = Args {
+            send_amount: None,
+            sweep: true,
+            fee_rate,
+            dust_policy,
+            ..sample_args()
+        };
+        let result = TxBuilder::<All>::new(&args);
+
+        match expected {
+            Some(expected) => {
+                let mut tx_builder = result.unwrap();
+                let tx = tx_builder
+                    .create_without_sig()
+                    .unwrap()
+                    .sign()
+                    .unwrap()
+                    .build()
+                    .unwrap();
+                assert_eq!(tx.0.output.len(), 1);
+                assert_eq!(tx.0.output[0].value, expected);
+                assert_eq!(tx.0.output[0].value, 4_847_873 - tx_builder.fee().unwrap());
+                assert_eq!(tx_builder.change(), None);
+            }
+            None => assert!(result.is_err()),
+        }
+    }
+
+    #[rstest]
+    // Change of 10_000 satoshi is kept regardless of the policy
+    #[case(DustPolicy::Drop, 20_000, Some((vec![9_000, 10_000], 1_000)))]
+    #[case(DustPolicy::Fail, 20_000, Some((vec![9_000, 10_000], 1_000)))]
+    #[case(DustPolicy::AddToOutput, 20_000, Some((vec![9_000, 10_000], 1_000)))]
+    // Change of 500 satoshi is below the P2PKH dust threshold (546 satoshi)
+    #[case(DustPolicy::Drop, 10_500, Some((vec![9_000], 1_500)))]
+    #[case(DustPolicy::Fail, 10_500, None)]
+    #[case(DustPolicy::AddToOutput, 10_500, Some((vec![9_500], 1_000)))]
+    // Change right at the dust threshold is kept, a satoshi less goes to the fee
+    #[case(DustPolicy::Drop, 10_546, Some((vec![9_000, 546], 1_000)))]
+    #[case(DustPolicy::Drop, 10_545, Some((vec![9_000], 1_545)))]
+    fn test_dust_policy(
+        #[case] dust_policy: DustPolicy,
+        #[case] utxo_amount: u64,
+        #[case] expected: Option<(Vec<u64>, u64)>,
+    ) {
+        let args = Args {
+            send_amount: Some(9_000),
+            utxo_amount: Some(utxo_amount),
+            dust_policy,
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let result = tx_builder.create_without_sig();
+
+        match expected {
+            Some(expected) => {
+                let transaction = result.unwrap().transaction.as_ref().unwrap();
+                let output_values: Vec<u64> = transaction.output.iter().map(|o| o.value).collect();
+                let fee = utxo_amount - output_values.iter().sum::<u64>();
+                assert_eq!((output_values, fee), expected)
+            }
+            None => assert!(result.is_err()),
+        }
+    }
+
+    #[rstest]
+    // Change of 500 satoshi
+    #[case(None, true)]
+    #[case(Some(500), true)]
+    #[case(Some(499), false)]
+    #[case(Some(0), false)]
+    fn test_change_dust_to_fee_max(#[case] max: Option<u64>, #[case] expected: bool) {
+        let args = Args {
+            send_amount: Some(9_000),
+            utxo_amount: Some(10_500),
+            change_dust_to_fee_max: max,
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        assert_eq!(tx_builder.create_without_sig().is_ok(), expected)
+    }
+
+    #[rstest]
+    // 1 P2PKH input, P2PKH destination and change
+    #[case("76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac", None, 1_000)]
+    #[case("76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac", Some(1.0), 226)]
+    #[case("76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac", Some(2.5), 565)]
+    // 1 P2WPKH input: 119 bytes + 110 weight units of witness = 146.5 vbytes
+    #[case("00143d927250d4a4744f5f99b499f750d85054dbf9fc", Some(1.0), 147)]
+    #[case("00143d927250d4a4744f5f99b499f750d85054dbf9fc", Some(10.0), 1_470)]
+    fn test_calc_fee(
+        #[case] utxo_script_pubkey: &str,
+        #[case] fee_rate: Option<f64>,
+        #[case] expected: u64,
+    ) {
+        let args = Args {
+            utxo_script_pubkey: Some(utxo_script_pubkey.to_string()),
+            change_matches: ChangeMatches::Source,
+            fee_rate,
+            ..sample_args()
+        };
+        let tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        assert_eq!(tx_builder.calc_fee(), expected)
+    }
+
+    #[rstest]
+    // 1 input and 2 outputs: 226 vbytes
+    #[case(1, vec![], Some(1.0), None, 226)]
+    #[case(1, vec![], Some(2.0), None, 452)]
+    // 2 inputs and 3 outputs: 408 vbytes
+    #[case(2, vec!["mqwpxxvfv3QbM8PU8uBx2jaNt9btQqvQNx:1000"], Some(1.0), None, 408)]
+    #[case(2, vec!["mqwpxxvfv3QbM8PU8uBx2jaNt9btQqvQNx:1000"], Some(2.0), None, 816)]
+    // `--fee` ignores the size
+    #[case(1, vec![], None, Some(300), 300)]
+    #[case(2, vec!["mqwpxxvfv3QbM8PU8uBx2jaNt9btQqvQNx:1000"], None, Some(300), 300)]
+    fn test_calc_fee_size(
+        #[case] inputs: u32,
+        #[case] outputs: Vec<&str>,
+        #[case] fee_rate: Option<f64>,
+        #[case] fee: Option<u64>,
+        #[case] expected: u64,
+    ) {
+        let args = Args {
+            utxo_txid: None,
+            utxo_tx_index: None,
+            utxo_amount: None,
+            utxo_script_pubkey: None,
+            utxo: (0..inputs)
+                .map(|vout| Prevout {
+                    txid: "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331"
+                        .to_string(),
+                    vout,
+                    amount: 10_000,
+                    script_pubkey: "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string(),
+                    sighash: None,
+                    private_key: None,
+                })
+                .collect(),
+            outputs: outputs.into_iter().map(|o| o.parse().unwrap()).collect(),
+            change_matches: ChangeMatches::Source,
+            fee_rate,
+            fee,
+            ..sample_args()
+        };
+        let tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        assert_eq!(tx_builder.calc_fee(), expected)
+    }
+
+    #[rstest]
+    // The default fee when neither `--fee` nor a rate is given
+    #[case(None, Ok(4_846_773))]
+    #[case(Some(2_000), Ok(4_845_773))]
+    // Leaves exactly nothing for the change
+    #[case(Some(4_847_773), Ok(0))]
+    #[case(
+        Some(4_847_800),
+        Err("insufficient funds: 4847900 satoshi needed, 4847873 satoshi available (27 satoshi short)")
+    )]
+    fn test_fixed_fee_change(#[case] fee: Option<u64>, #[case] expected: Result<u64, &str>) {
+        let args = Args {
+            fee,
+            force: true,
+            ..sample_args()
+        };
+        let tx_builder = TxBuilder::<All>::new(&args).unwrap();
+
+        assert_eq!(
+            tx_builder.calc_change_amount().map_err(|e| e.to_string()),
+            expected.map_err(str::to_string)
+        );
+    }
+
+    #[rstest]
+    // 4_847_873 - 1_000 fee - send amount = change
+    #[case(None, 4_846_173, None, Ok((4_846_173, Some(700))))]
+    #[case(Some(None), 4_846_173, None, Ok((4_846_173, Some(700))))]
+    #[case(Some(None), 4_846_473, None, Ok((4_846_873, None)))]
+    #[case(Some(Some(1_000)), 4_846_173, None, Ok((4_846_873, None)))]
+    #[case(Some(Some(1_000)), 4_845_873, None, Ok((4_845_873, Some(1_000))))]
+    #[case(
+        Some(Some(1_000)),
+        4_846_173,
+        Some(4_846_500),
+        Err(
+            "destination output of 4846873 satoshi (4846173 satoshi plus 700 satoshi of change) \
+             exceeds `--max-destination-amount` of 4846500 satoshi"
+        )
+    )]
+    fn test_combine_dust(
+        #[case] combine_dust: Option<Option<u64>>,
+        #[case] send_amount: u64,
+        #[case] max_destination_amount: Option<u64>,
+        #[case] expected: Result<(u64, Option<u64>), &str>,
+    ) {
+        let args = Args {
+            send_amount: Some(send_amount),
+            combine_dust,
+            max_destination_amount,
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let result = tx_builder
+            .create_without_sig()
+            .map(|tx_builder| tx_builder.build().unwrap())
+            .map(|tx| (tx.0.output[0].value, tx.0.output.get(1).map(|o| o.value)));
+        assert_eq!(
+            result.map_err(|e| e.to_string()),
+            expected.map_err(str::to_string)
+        )
+    }
+
+    #[rstest]
+    #[case(DustPolicy::Drop, None, 100, "created", "1000")]
+    #[case(DustPolicy::Drop, None, 4_846_773, "dropped", "1100")]
+    #[case(DustPolicy::AddToOutput, None, 4_846_773, "added_to_output", "1000")]
+    #[case(DustPolicy::Fail, Some(None), 4_846_773, "combined", "1000")]
+    fn test_explain(
+        #[case] dust_policy: DustPolicy,
+        #[case] combine_dust: Option<Option<u64>>,
+        #[case] send_amount: u64,
+        #[case] expected_outcome: &str,
+        #[case] expected_fee: &str,
+    ) {
+        let args = Args {
+            send_amount: Some(send_amount),
+            dust_policy,
+            combine_dust,
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        tx_builder.create_without_sig().unwrap();
+        let explanation = tx_builder.explain("given").unwrap();
+
+        let field = |path: &[&str]| {
+            path.iter()
+                .try_fold(&explanation, |value, key| value.get(key))
+                .cloned()
+        };
+        assert_eq!(
+            field(&["change", "outcome"]),
+            Some(Value::String(expected_outcome.to_string()))
+        );
+        assert_eq!(
+            field(&["fee", "method"]),
+            Some(Value::String("flat".to_string()))
+        );
+        assert_eq!(
+            field(&["balance", "fee"]),
+            Some(Value::Number(expected_fee.to_string()))
+        );
+        assert_eq!(field(&["balance", "ok"]), Some(Value::Bool(true)));
+        let Some(Value::Array(inputs)) = field(&["inputs"]) else {
+            panic!("no inputs")
+        };
+        assert_eq!(
+            inputs[0].get("sighash_type"),
+            Some(&Value::String("SIGHASH_ALL".to_string()))
+        );
+    }
+
+    #[rstest]
+    #[case(DustPolicy::Drop, 4_846_773, None)]
+    // 100 satoshi of dust change go to the destination
+    #[case(
+        DustPolicy::AddToOutput,
+        4_846_773,
+        Some("destination output is 4846873 satoshi instead of the requested 4846773 satoshi (+100 satoshi)")
+    )]
+    #[case(DustPolicy::AddToOutput, 100, None)]
+    fn test_send_amount_warning(
+        #[case] dust_policy: DustPolicy,
+        #[case] send_amount: u64,
+        #[case] expected: Option<&str>,
+    ) {
+        let args = Args {
+            send_amount: Some(send_amount),
+            dust_policy,
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let tx = tx_builder.create_without_sig().unwrap().build().unwrap();
+        assert_eq!(
+            tx_builder.send_amount_warning(tx.0.output[0].value),
+            expected.map(str::to_string)
+        )
+    }
+
+    #[rstest]
+    fn test_send_amount_warning_reserve() {
+        let args = Args {
+            send_amount: None,
+            reserve: Some(10_000),
+            ..sample_args()
+        };
+        let tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        assert_eq!(
+            tx_builder.send_amount_warning(4_836_873),
+            Some(
+                "destination output is 4836873 satoshi, everything left after `--reserve` and the fee"
+                    .to_string()
+            )
+        )
+    }
+
+    #[rstest]
+    #[case(
+        DustPolicy::Drop,
+        4_846_773,
+        "amount: 4846773 satoshi\nfee: 1100 satoshi\nchange: 0 satoshi"
+    )]
+    #[case(
+        DustPolicy::Drop,
+        100,
+        "amount: 100 satoshi\nfee: 1000 satoshi\nchange: 4846773 satoshi"
+    )]
+    #[case(
+        DustPolicy::AddToOutput,
+        4_846_773,
+        "amount: 4846873 satoshi\nfee: 1000 satoshi\nchange: 0 satoshi"
+    )]
+    fn test_summary(
+        #[case] dust_policy: DustPolicy,
+        #[case] send_amount: u64,
+        #[case] expected: &str,
+    ) {
+        let args = Args {
+            send_amount: Some(send_amount),
+            dust_policy,
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        tx_builder.create_without_sig().unwrap();
+        assert_eq!(
+            tx_builder.summary(&args.destination()).unwrap(),
+            format!(
+                "destination: mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn\n{}",
+                expected
+            )
+        )
+    }
+
+    #[rstest]
+    #[case(OutputOrder::ChangeFirst)]
+    #[case(OutputOrder::Bip69)]
+    fn test_summary_change_to_destination(#[case] output_order: OutputOrder) {
+        // The change goes back to the source, which is also the destination
+        let args = Args {
+            destination_address: Some("mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string()),
+            output_order,
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        tx_builder.create_without_sig().unwrap();
+        assert_eq!(
+            tx_builder.summary(&args.destination()).unwrap(),
+            "destination: mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx\namount: 100 satoshi\n\
+             fee: 1000 satoshi\nchange: 4846773 satoshi"
+        )
+    }
+
+    #[rstest]
+    #[case(1.0, 1_000)]
+    #[case(2.5, 2_500)]
+    #[case(0.0001, 226)] // 10 satoshi, below the 226-vbyte minimum relay fee
+    #[case(0.0, 226)]
+    fn test_calc_fee_percent(#[case] fee_percent: f64, #[case] expected: u64) {
+        let args = Args {
+            send_amount: Some(100_000),
+            fee_percent: Some(fee_percent),
+            ..sample_args()
+        };
+        let tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        assert_eq!(tx_builder.calc_fee(), expected)
+    }
+
+    #[rstest]
+    // 226 vbytes, P2PKH dust threshold of 546 satoshi
+    #[case(10_000, 1.0, 226, Some(9_674), false)]
+    #[case(10_000, 40.0, 9_040, Some(860), false)]
+    #[case(10_000, 42.0, 9_492, Some(408), true)]
+    #[case(10_000, 50.0, 11_300, None, false)]
+    fn test_fee_table(
+        #[case] utxo_amount: u64,
+        #[case] fee_rate: f64,
+        #[case] expected_fee: u64,
+        #[case] expected_change: Option<u64>,
+        #[case] expected_dust: bool,
+    ) {
+        let args = Args {
+            utxo_amount: Some(utxo_amount),
+            ..sample_args()
+        };
+        let tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        assert_eq!(
+            tx_builder.fee_table(&[fee_rate]),
+            vec![FeeEstimate {
+                fee_rate,
+                fee: expected_fee,
+                change: expected_change,
+                dust: expected_dust,
+            }]
+        )
+    }
+
+    #[rstest]
+    #[case(0)]
+    #[case(100)]
+    fn test_fixed_fee_below_relay_fee(#[case] fee: u64) {
+        let args = Args {
+            fee: Some(fee),
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let tx = tx_builder
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(tx_builder.fee().unwrap(), fee);
+        assert_eq!(tx_builder.change(), Some(4_847_873 - 100 - fee));
+        assert_eq!(tx.0.output[1].value, 4_847_873 - 100 - fee);
+        assert_eq!(tx_builder.relay_fee_error(), None);
+    }
+
+    #[rstest]
+    // 226 vbytes
+    #[case(None, false)]
+    #[case(Some(1.0), false)]
+    #[case(Some(0.5), true)]
+    #[case(Some(0.0), true)]
+    fn test_relay_fee_error(#[case] fee_rate: Option<f64>, #[case] expected: bool) {
+        let args = Args {
+            fee_rate,
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        tx_builder.create_without_sig().unwrap();
+
+        assert_eq!(tx_builder.relay_fee_error().is_some(), expected)
+    }
+
+    #[rstest]
+    #[case(false, 0, Sequence::MAX)]
+    #[case(true, 0, Sequence(0xfffffffd))]
+    #[case(false, 5, Sequence(0xfffffffe))]
+    #[case(true, 5, Sequence(0xfffffffd))]
+    fn test_sequence(#[case] rbf: bool, #[case] lock_time: u32, #[case] expected: Sequence) {
+        let args = Args {
+            rbf,
+            lock_time,
+            ..sample_args()
+        };
+        let tx = TxBuilder::<All>::new(&args)
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(tx.0.input[0].sequence, expected);
+        assert_eq!(tx.0.is_explicitly_rbf(), rbf);
+    }
+
+    #[rstest]
+    #[case(vec![144], 2, 0, false, Ok([144, 0xffffffff]))]
+    #[case(vec![0xfffffffe, 0x400001], 2, 5, false, Ok([0xfffffffe, 0x400001]))]
+    #[case(vec![0xffffffff], 1, 0, true, Ok([0xffffffff, 0xfffffffd]))]
+    #[case(vec![144], 1, 0, false, Err("sequence 0x90 of input 0 is a relative timelock"))]
+    #[case(vec![0xffffffff, 0xffffffff], 1, 5, false, Err("locktime 5 is ignored"))]
+    #[case(vec![0xffffffff, 0xfffffffe], 1, 0, true, Err("`--rbf` is set"))]
+    fn test_input_sequences(
+        #[case] sequence: Vec<u32>,
+        #[case] tx_version: i32,
+        #[case] lock_time: u32,
+        #[case] rbf: bool,
+        #[case] expected: Result<[u32; 2], &str>,
+    ) {
+        let utxo = |vout: u32| Prevout {
+            txid: "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331".to_string(),
+            vout,
+            amount: 3_000,
+            script_pubkey: "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string(),
+            sighash: None,
+            private_key: None,
+        };
+        let args = Args {
+            utxo_txid: None,
+            utxo_tx_index: None,
+            utxo_amount: None,
+            utxo_script_pubkey: None,
+            utxo: vec![utxo(0), utxo(1)],
+            sequence,
+            tx_version: Some(tx_version),
+            lock_time,
+            rbf,
+            ..sample_args()
+        };
+
+        match (TxBuilder::<All>::new(&args), expected) {
+            (Ok(mut tx_builder), Ok(expected)) => {
+                tx_builder.create_without_sig().unwrap().sign().unwrap();
+                // The sequences are signed, so the signatures must verify with them in place
+                tx_builder.verify().unwrap();
+                let tx = tx_builder.build().unwrap();
+                let sequences: Vec<_> = tx.0.input.iter().map(|input| input.sequence.0).collect();
+                assert_eq!(sequences, expected);
+            }
+            (Err(error), Err(expected)) => assert!(
+                error.to_string().starts_with(expected),
+                "unexpected error: {}",
+                error
+            ),
+            (result, expected) => panic!("got {:?}, expected {:?}", result.err(), expected),
+        }
+    }
+
+    #[rstest]
+    #[case(None, "0x01000000")]
+    #[case(Some(1), "0x01000000")]
+    #[case(Some(2), "0x02000000")]
+    fn test_tx_version(#[case] tx_version: Option<i32>, #[case] expected: &str) {
+        let args = Args {
+            tx_version,
+            ..sample_args()
+        };
+        let tx = TxBuilder::<All>::new(&args)
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(tx.output().starts_with(expected))
+    }
+
+    #[rstest]
+    #[case(800_000, true)]
+    #[case(1_700_000_000, false)]
+    fn test_lock_time(#[case] lock_time: u32, #[case] is_block_height: bool) {
+        let args = Args {
+            lock_time,
+            ..sample_args()
+        };
+        let tx = TxBuilder::<All>::new(&args)
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(tx.0.lock_time.is_block_height(), is_block_height);
+        assert_eq!(tx.0.lock_time.to_consensus_u32(), lock_time);
+        assert_eq!(tx.0.input[0].sequence, Sequence(0xfffffffe));
+        assert!(tx.0.is_lock_time_enabled());
+    }
+
+    #[rstest]
+    // Pinned so that changing the default version, sequence or locktime can't go unnoticed
+    #[case(0, "010000000131c3a964f8a8176863a122142ca7db8c173769175aaefbb5160359ada9be3ed70100000000ffffffff0264000000000000001976a914a997f6d478624028ea1f36082e7ceb5d79d7567188acb5f44900000000001976a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac00000000")]
+    #[case(5, "010000000131c3a964f8a8176863a122142ca7db8c173769175aaefbb5160359ada9be3ed70100000000feffffff0264000000000000001976a914a997f6d478624028ea1f36082e7ceb5d79d7567188acb5f44900000000001976a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac05000000")]
+    fn test_unsigned_transaction(#[case] lock_time: u32, #[case] expected: &str) {
+        let args = Args {
+            lock_time,
+            ..sample_args()
+        };
+        for _ in 0..2 {
+            let tx = TxBuilder::<All>::new(&args)
+                .unwrap()
+                .create_without_sig()
+                .unwrap()
+                .build()
+                .unwrap();
+            assert_eq!(tx.output(), format!("0x{}", expected));
+        }
+    }
+
+    #[rstest]
+    fn test_estimated_vsize() {
+        let mut tx_builder = TxBuilder::<All>::new(&sample_args()).unwrap();
+        let tx = tx_builder
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap();
+
+        // The estimate assumes the largest common signature, so it may overshoot by a byte
+        let estimated = tx_builder.estimated_vsize();
+        assert!((tx.0.vsize()..=tx.0.vsize() + 1).contains(&estimated));
+    }
+
+    #[rstest]
+    // P2PKH
+    #[case("76a914000000000000000000000000000000000000000088ac", false, 226)]
+    // Uncompressed keys are 32 bytes longer
+    #[case("76a914000000000000000000000000000000000000000088ac", true, 258)]
+    // P2WPKH
+    #[case("00140000000000000000000000000000000000000000", false, 147)]
+    // P2SH-P2WPKH
+    #[case("a914000000000000000000000000000000000000000087", false, 170)]
+    // P2TR
+    #[case(
+        "51200000000000000000000000000000000000000000000000000000000000000000",
+        false,
+        136
+    )]
+    fn test_estimated_vsize_input_types(
+        #[case] template: &str,
+        #[case] uncompressed: bool,
+        #[case] expected: usize,
+    ) {
+        let secp = Secp256k1::new();
+        let mut key =
+            PrivateKey::from_wif("cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP").unwrap();
+        key.compressed = !uncompressed;
+        let script_pubkey = TxBuilder::<All>::derive_script_pubkey(
+            &secp,
+            &key.public_key(&secp),
+            &ScriptBuf::from_hex(template).unwrap(),
+        )
+        .unwrap();
+        let args = Args {
+            source_address: match uncompressed {
+                true => "n1S9T9p9WJss8d1eQsheokZjGfAQyEtchg".to_string(),
+                false => "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string(),
+            },
+            utxo_script_pubkey: Some(script_pubkey.to_hex_string()),
+            uncompressed,
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        // Before the transaction exists
+        let estimated = tx_builder.estimated_vsize();
+        assert_eq!(estimated, expected);
+
+        let tx = tx_builder
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!((tx.0.vsize()..=tx.0.vsize() + 1).contains(&estimated));
+    }
+
+    #[rstest]
+    #[case(0, true)]
+    #[case(1, true)]
+    #[case(2, false)]
+    fn test_check_prev_txs(#[case] vout: u32, #[case] expected: bool) {
+        // A transaction with 2 outputs
+        let prev_tx = TxBuilder::<All>::new(&sample_args())
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .build()
+            .unwrap()
+            .0;
+        let args = Args {
+            utxo_txid: Some(prev_tx.txid().to_string()),
+            utxo_tx_index: Some(vout),
+            ..sample_args()
+        };
+        let result = TxBuilder::<All>::new(&args)
+            .unwrap()
+            .check_prev_txs(|txid| Ok((*txid == prev_tx.txid()).then(|| prev_tx.clone())));
+
+        match expected {
+            true => assert!(result.is_ok()),
+            false => assert_eq!(
+                result.unwrap_err().to_string(),
+                "vout 2 out of range (tx has 2 outputs)"
+            ),
+        }
+    }
+
+    #[rstest]
+    fn test_check_prev_txs_txid() {
+        let prev_tx = TxBuilder::<All>::new(&sample_args())
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .build()
+            .unwrap()
+            .0;
+        // A source answering with some other transaction
+        let error = TxBuilder::<All>::new(&sample_args())
+            .unwrap()
+            .check_prev_txs(|_| Ok(Some(prev_tx.clone())))
+            .unwrap_err();
+
+        assert!(error
+            .to_string()
+            .starts_with("previous transaction of input 0 has txid"));
+    }
+
+    #[rstest]
+    #[case(vec![], vec![4000, 6000])]
+    #[case(vec![4000], vec![4000, 6000])]
+    #[case(vec![4500, 5500], vec![4500, 5500])]
+    fn test_input_amount(#[case] input_amount: Vec<u64>, #[case] expected: Vec<u64>) {
+        let args = Args {
+            utxo_txid: None,
+            utxo_tx_index: None,
+            utxo_amount: None,
+            utxo_script_pubkey: None,
+            prevouts: Some(
+                r#"[
+                    {"txid": "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331", "vout": 1, "amount": 4000, "script_pubkey": "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac"},
+                    {"txid": "2a4bb3ed0533d9e1d4c896003e4ed0a1378e128c984632a0080b491ba316f3eb", "vout": 0, "amount": 6000, "script_pubkey": "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac"}
+                ]"#
+                .to_string().into()
+            ),
+            input_amount,
+            ..sample_args()
+        };
+        let tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let amounts: Vec<u64> = tx_builder.utxos.iter().map(|utxo| utxo.amount).collect();
+
+        assert_eq!(amounts, expected)
+    }
+
+    #[rstest]
+    #[case(10_000, 500, Some(8_500))]
+    #[case(1_100, 100, Some(0))]
+    #[case(1_099, 100, None)]
+    fn test_is_funded(
+        #[case] utxo_amount: u64,
+        #[case] send_amount: u64,
+        #[case] expected: Option<u64>,
+    ) {
+        let args = Args {
+            send_amount: Some(send_amount),
+            utxo_amount: Some(utxo_amount),
+            ..sample_args()
+        };
+        let tx_builder = TxBuilder::<All>::new(&args).unwrap();
+
+        assert_eq!(tx_builder.is_funded(), expected.is_some());
+        assert_eq!(tx_builder.funded_change(), expected)
+    }
+
+    #[rstest]
+    #[case(
+        ArgsNetwork::Testnet,
+        "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP",
+        "mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn",
+        Ok(())
+    )]
+    // Testnet WIFs and base58 addresses are shared with regtest and signet
+    #[case(
+        ArgsNetwork::Regtest,
+        "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP",
+        "mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn",
+        Ok(())
+    )]
+    #[case(
+        ArgsNetwork::Mainnet,
+        "L4p2b9VAf8k5aUahF1JCJUzZkgNEAqLfq8DDdQiyAprQAKSbu8hf",
+        "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA",
+        Err("`--source-address` is not a bitcoin address")
+    )]
+    #[case(
+        ArgsNetwork::Mainnet,
+        "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP",
+        "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA",
+        Err("`--private-key` is for testnet, not bitcoin")
+    )]
+    #[case(
+        ArgsNetwork::Testnet,
+        "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP",
+        "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA",
+        Err("`--destination-address` is not a testnet address")
+    )]
+    // Hex keys take the network from `--network`
+    #[case(
+        ArgsNetwork::Mainnet,
+        "0000000000000000000000000000000000000000000000000000000000000001",
+        "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA",
+        Err("`--source-address` is not a bitcoin address")
+    )]
+    fn test_network(
+        #[case] network: ArgsNetwork,
+        #[case] private_key: &str,
+        #[case] destination_address: &str,
+        #[case] expected: Result<(), &str>,
+    ) {
+        let args = Args {
+            network: Some(network),
+            private_key: Some(private_key.into()),
+            destination_address: Some(destination_address.to_string()),
+            ..sample_args()
+        };
+        assert_eq!(
+            TxBuilder::<All>::new(&args)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            expected.map_err(str::to_string)
+        )
+    }
+
+    #[rstest]
+    #[case(
+        "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP",
+        "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx",
+        true
+    )]
+    #[case(
+        "KyRv5iFPHG7iB5E4CqvMzH3WFJVhbfYK4VY7XAedd9Ys69mEsPLQ",
+        "1NsJS4DAHLcegD63trZurmkLm23TRAujXd",
+        false
+    )]
+    fn test_ensure_key_network(
+        #[case] private_key: &str,
+        #[case] source_address: &str,
+        #[case] expected: bool,
+    ) {
+        let args = Args {
+            private_key: Some(private_key.into()),
+            source_address: source_address.to_string(),
+            utxo_script_pubkey: Some(
+                Address::from_str(source_address)
+                    .unwrap()
+                    .assume_checked()
+                    .script_pubkey()
+                    .to_hex_string(),
+            ),
+            ..sample_args()
+        };
+        let tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        assert_eq!(
+            tx_builder.ensure_key_network(Network::Testnet).is_ok(),
+            expected
+        )
+    }
+
+    #[rstest]
+    // P2PKH
+    #[case(
+        "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx",
+        Network::Testnet,
+        Some("76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac")
+    )]
+    // P2SH
+    #[case(
+        "2MtFuGa67i3Rsvo6YAffgqHeEjuArDWdZJT",
+        Network::Testnet,
+        Some("a9140b18ea6f1de6ef6513e23971bab139a5c09170bc87")
+    )]
+    // P2WPKH
+    #[case(
+        "tb1q8kf8y5x5536y7huekjvlw5xc2p2dh70ushlq70",
+        Network::Testnet,
+        Some("00143d927250d4a4744f5f99b499f750d85054dbf9fc")
+    )]
+    // P2WSH
+    #[case(
+        "tb1qft5p2uhsdcdc3l2ua4ap5qqfg4pjaqlp250x7us7a8qqhrxrxfsqaqh7jw",
+        Network::Testnet,
+        Some("00204ae81572f06e1b88fd5ced7a1a000945432e83e1551e6f721ee9c00b8cc33260")
+    )]
+    // P2TR
+    #[case(
+        "tb1ppmvzd538z4xmt94ysf6wu9enxr72lnq5d0gmew35u6l0u74y8zaqxhq7ja",
+        Network::Testnet,
+        Some("51200ed826d227154db596a48274ee173330fcafcc146bd1bcba34e6befe7aa438ba")
+    )]
+    // Wrong network
+    #[case("mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx", Network::Bitcoin, None)]
+    #[case("mm8Wx3H3b3est26kxN1XY6sTnYNkxX16L", Network::Testnet, None)]
+    fn test_address_script_pubkey(
+        #[case] address: &str,
+        #[case] network: Network,
+        #[case] expected: Option<&str>,
+    ) {
+        assert_eq!(
+            address_script_pubkey(address, network)
+                .ok()
+                .map(|script_pubkey| script_pubkey.to_hex_string()),
+            expected.map(str::to_string)
+        )
+    }
+
+    #[rstest]
+    #[case("mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx", Ok(()))]
+    // The P2WPKH address of the same key
+    #[case("tb1q8kf8y5x5536y7huekjvlw5xc2p2dh70ushlq70", Ok(()))]
+    #[case(
+        "mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn",
+        Err("the private key controls mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx, not `--source-address` mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn")
+    )]
+    // P2WSH can't be derived from a single key
+    #[case(
+        "tb1qft5p2uhsdcdc3l2ua4ap5qqfg4pjaqlp250x7us7a8qqhrxrxfsqaqh7jw",
+        Ok(())
+    )]
+    fn test_source_address_key(#[case] source_address: &str, #[case] expected: Result<(), &str>) {
+        let args = Args {
+            source_address: source_address.to_string(),
+            ..sample_args()
+        };
+        assert_eq!(
+            TxBuilder::<All>::new(&args)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            expected.map_err(str::to_string)
+        )
+    }
+
+    #[rstest]
+    #[case("76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac", true)]
+    // P2PKH of another key
+    #[case("76a914a997f6d478624028ea1f36082e7ceb5d79d7567188ac", false)]
+    // P2WPKH of another key
+    #[case("0014a997f6d478624028ea1f36082e7ceb5d79d75671", false)]
+    // P2SH could wrap the key of the source address, so it is let through
+    #[case("a9144733f37cf4db86fbc2efed2500b4f4e49f31202387", true)]
+    fn test_utxo_script_pubkey_source(#[case] script_pubkey: &str, #[case] expected: bool) {
+        let args = Args {
+            utxo_script_pubkey: Some(script_pubkey.to_string()),
+            ..sample_args()
+        };
+        let result = TxBuilder::<All>::new(&args).map(|_| ());
+
+        match expected {
+            true => assert!(result.is_ok()),
+            false => assert_eq!(
+                result.unwrap_err().to_string(),
+                format!(
+                    "`--utxo-script-pubkey` {} does not belong to `--source-address` \
+                     mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx",
+                    script_pubkey
+                )
+            ),
+        }
+    }
+
+    #[rstest]
+    #[case(
+        "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP",
+        None,
+        false,
+        Some("mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx")
+    )]
+    #[case(
+        "233aad6bb28a67e1a22f0d808ac73abffa2fa1bca56fddfcae0e9bd1c242d6d6",
+        None,
+        false,
+        Some("mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx")
+    )]
+    #[case(
+        "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP",
+        Some(Network::Testnet),
+        true,
+        Some("n1S9T9p9WJss8d1eQsheokZjGfAQyEtchg")
+    )]
+    #[case(
+        "L4p2b9VAf8k5aUahF1JCJUzZkgNEAqLfq8DDdQiyAprQAKSbu8hf",
+        Some(Network::Bitcoin),
+        false,
+        Some("1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA")
+    )]
+    // Wrong network
+    #[case(
+        "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP",
+        Some(Network::Bitcoin),
+        false,
+        None
+    )]
+    #[case(
+        "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJ",
+        None,
+        false,
+        None
+    )]
+    fn test_derive_address(
+        #[case] key: &str,
+        #[case] network: Option<Network>,
+        #[case] uncompressed: bool,
+        #[case] expected: Option<&str>,
+    ) {
+        assert_eq!(
+            derive_address(key, KeyFormat::Auto, network, uncompressed)
+                .ok()
+                .map(|address| address.to_string()),
+            expected.map(str::to_string)
+        )
+    }
+
+    #[rstest]
+    #[case(
+        "76a914a997f6d478624028ea1f36082e7ceb5d79d7567188ac",
+        Network::Testnet,
+        Some("mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn")
+    )]
+    // BIP173 test vector
+    #[case(
+        "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+        Network::Bitcoin,
+        Some("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4")
+    )]
+    #[case(
+        "5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c",
+        Network::Bitcoin,
+        Some("bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr")
+    )]
+    #[case("6a0568656c6c6f", Network::Testnet, None)]
+    fn test_script_address(
+        #[case] script_pubkey: &str,
+        #[case] network: Network,
+        #[case] expected: Option<&str>,
+    ) {
+        let script_pubkey = ScriptBuf::from_hex(script_pubkey).unwrap();
+        assert_eq!(
+            script_address(&script_pubkey, network).map(|address| address.to_string()),
+            expected.map(str::to_string)
+        )
+    }
+
+    #[rstest]
+    #[case(
+        4_847_873,
+        "outputs: 4846873 satoshi\nchange: 4846773 satoshi\nfee: 1000 satoshi (4.44 sat/vB)"
+    )]
+    // Change of 500 satoshi is dust and goes to the fee, with the change output left out
+    #[case(
+        1_600,
+        "outputs: 100 satoshi\nchange: none\nfee: 1500 satoshi (7.81 sat/vB)"
+    )]
+    fn test_tx_summary(#[case] utxo_amount: u64, #[case] expected: &str) {
+        let args = Args {
+            utxo_amount: Some(utxo_amount),
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let tx = tx_builder
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap();
+        let summary = tx.summary(tx_builder.utxo_amount(), tx_builder.change());
+
+        assert!(summary.starts_with(&format!(
+            "vsize: {} vbytes\nweight: {} WU\ninputs: {} satoshi\n",
+            tx.0.vsize(),
+            tx.0.weight().to_wu(),
+            utxo_amount
+        )));
+        assert!(summary.ends_with(expected));
+    }
+
+    #[rstest]
+    fn test_output_json() {
+        let tx = TxBuilder::<All>::new(&sample_args())
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap();
+        // Round-trip through the JSON text, as another tool would receive it
+        let json = Value::parse(&tx.output_json(Network::Testnet).to_string()).unwrap();
+        let outputs = json.get("outputs").unwrap().as_array().unwrap();
+
+        assert_eq!(
+            json.get("txid").unwrap().as_str(),
+            Some(tx.0.txid().to_string().as_str())
+        );
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(
+            outputs[0].get("address").unwrap().as_str(),
+            Some("mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn")
+        );
+        // Change back to the P2PKH source
+        assert_eq!(
+            outputs[1].get("script_pubkey_asm").unwrap().as_str(),
+            Some(
+                "OP_DUP OP_HASH160 OP_PUSHBYTES_20 3d927250d4a4744f5f99b499f750d85054dbf9fc \
+                 OP_EQUALVERIFY OP_CHECKSIG"
+            )
+        );
+        let script_sig_asm = json.get("inputs").unwrap().as_array().unwrap()[0]
+            .get("script_sig_asm")
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert!(
+            script_sig_asm.starts_with("OP_PUSHBYTES_72 ")
+                || script_sig_asm.starts_with("OP_PUSHBYTES_71 ")
+        );
+        assert!(script_sig_asm.contains(" OP_PUSHBYTES_33 "));
+        assert_eq!(json.get("hex").unwrap().as_str(), Some(tx.hex().as_str()));
+    }
+
+    #[rstest]
+    fn test_decode_transaction() {
+        let tx = TxBuilder::<All>::new(&sample_args())
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap();
+        let decoded = decode_transaction(tx.output().trim_start_matches("0x")).unwrap();
+
+        assert_eq!(decoded, tx.0);
+        assert!(decode_transaction("0100").is_err());
+    }
+
+    #[rstest]
+    fn test_txid() {
+        // The P2SH-P2WPKH example of BIP143
+        let tx = Tx::decode("01000000000101db6b1b20aa0fd7b23880be2ecbd4a98130974cf4748fb66092ac4d3ceb1a5477010000001716001479091972186c449eb1ded22b78e40d009bdf0089feffffff02b8b4eb0b000000001976a914a457b684d7f0d539a46a45bbc043f35b59d0d96388ac0008af2f000000001976a914fd270b1ee6abcaea97fea7ad0402e8bd8ad6d77c88ac02473044022047ac8e878352d3ebbde1c94ce3a10d057c24175747116f8288e5d794d12d482f0220217f36a485cae903c713331d877c1f64677e3622ad4010726870540656fe9dcb012103ad1d8e89212f0b92c74d23bb710c00662ad1470198ac48c43f7d6f93a2a2687392040000").unwrap();
+
+        assert_eq!(
+            tx.txid().to_string(),
+            "ef48d9d0f595052e0f8cdcf825f7a5e50b6a388a81f206f3f4846e5ecd7a0c23"
+        );
+        assert_eq!(
+            tx.0.wtxid().to_string(),
+            "680f483b2bf6c5dcbf111e69e885ba248a41a5e92070cfb0afec3cfc49a9fabb"
+        );
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("0x")]
+    fn test_tx_decode(#[case] prefix: &str) {
+        // The sample transaction in the README
+        let hex = "01000000011798d99e33691fe595ac0fb00224adf249657b5a8d8cf7574928accce7c4d70e010000006a473044022053f663276bf1673a32f55d213428983d5fdfa7146ac3884439475ee90257c21b02206b1ecc97e8601a67eaf13067a70386737b0f7d59b7e44817d927db17829275b301210303998660a6a026b2f8aa72d37a077b6a76b282b2d5b73fc582fdc274f66fa5bcffffffff0264000000000000001976a914a997f6d478624028ea1f36082e7ceb5d79d7567188acd41d0000000000001976a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac00000000";
+        let tx = Tx::decode(&format!("{}{}", prefix, hex)).unwrap();
+        let json = tx.output_json(Network::Testnet);
+        let outputs = json.get("outputs").unwrap().as_array().unwrap();
+
+        assert_eq!(
+            json.get("txid").unwrap().as_str(),
+            Some("6228dedb6c7f743114093934fce3577013d0673b9688ac1df30a942ca0bd999f")
+        );
+        assert_eq!(outputs[1].get("value").unwrap().as_u64(), Some(7636));
+        assert_eq!(
+            outputs[1].get("address").unwrap().as_str(),
+            Some("mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx")
+        );
+        assert_eq!(json.get("hex").unwrap().as_str(), Some(hex));
+    }
+
+    #[rstest]
+    // Key 1 for input 0, key 2 for input 1
+    #[case("76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac", true)]
+    // Neither key matches input 0
+    #[case("76a914a997f6d478624028ea1f36082e7ceb5d79d7567188ac", false)]
+    fn test_keys_file(#[case] script_pubkey: &str, #[case] expected: bool) {
+        let keys_file = std::env::temp_dir().join(format!(
+            "generate-btc-transaction-keys-{}-{}",
+            std::process::id(),
+            expected
+        ));
+        std::fs::write(
+            &keys_file,
+            "# second key\ncVt4o7BGAig1UXywgGSmARhxMdzP5qvQsxKkSsc1XEkw3tDTQFpy\n",
+        )
+        .unwrap();
+        let prevout = |script_pubkey: &str| {
+            format!(
+                r#"{{"txid": "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331", "vout": 1, "amount": 4847873, "script_pubkey": "{}"}}"#,
+                script_pubkey
+            )
+        };
+        let args = Args {
+            utxo_txid: None,
+            utxo_tx_index: None,
+            utxo_amount: None,
+            utxo_script_pubkey: None,
+            prevouts: Some(
+                format!(
+                    "[{}, {}]",
+                    prevout(script_pubkey),
+                    prevout("76a914726589f17c655b20a803f4599931907a050d078588ac")
+                )
+                .into(),
+            ),
+            keys_file: Some(keys_file.clone()),
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let result = tx_builder.create_without_sig().unwrap().sign();
+        std::fs::remove_file(keys_file).unwrap();
+
+        match expected {
+            true => {
+                let tx = result.unwrap().build().unwrap();
+                let pubkeys: Vec<String> =
+                    tx.0.input
+                        .iter()
+                        .map(|input| {
+                            let script_sig = input.script_sig.as_bytes();
+                            hex::encode(&script_sig[script_sig.len() - 33..])
+                        })
+                        .collect();
+                assert_eq!(
+                    pubkeys,
+                    vec![
+                        "0303998660a6a026b2f8aa72d37a077b6a76b282b2d5b73fc582fdc274f66fa5bc",
+                        "039b6347398505f5ec93826dc61c19f47c66c0283ee9be980e29ce325a0f4679ef"
+                    ]
+                )
+            }
+            false => assert!(result.is_err()),
+        }
+    }
+
+    #[rstest]
+    // Each input signed by the key given with it
+    #[case(
+        "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP",
+        "cVt4o7BGAig1UXywgGSmARhxMdzP5qvQsxKkSsc1XEkw3tDTQFpy",
+        Ok(())
+    )]
+    // The keys swapped
+    #[case(
+        "cVt4o7BGAig1UXywgGSmARhxMdzP5qvQsxKkSsc1XEkw3tDTQFpy",
+        "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP",
+        Err("cannot sign input 0: its private key does not match its scriptPubKey")
+    )]
+    fn test_input_keys(#[case] key0: &str, #[case] key1: &str, #[case] expected: Result<(), &str>) {
+        let prevout = |vout: u32, script_pubkey: &str, key: &str| {
+            format!(
+                r#"{{"txid": "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331", "vout": {}, "amount": 4847873, "script_pubkey": "{}", "private_key": "{}"}}"#,
+                vout, script_pubkey, key
+            )
+        };
+        let args = Args {
+            private_key: None,
+            utxo_txid: None,
+            utxo_tx_index: None,
+            utxo_amount: None,
+            utxo_script_pubkey: None,
+            prevouts: Some(
+                format!(
+                    "[{}, {}]",
+                    prevout(
+                        1,
+                        "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac",
+                        key0
+                    ),
+                    prevout(
+                        2,
+                        "76a914726589f17c655b20a803f4599931907a050d078588ac",
+                        key1
+                    )
+                )
+                .into(),
+            ),
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let result = tx_builder
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .and_then(|tx_builder| tx_builder.verify().map(|_| ()));
+
+        assert_eq!(
+            result.map_err(|e| e.to_string()),
+            expected.map_err(str::to_string)
+        );
+    }
+
+    #[rstest]
+    #[case("cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP", true)]
+    #[case(
+        "1b2a2b7e5b2be9a2a0ac40bd24ded0149b52302e3fbf4a7c71a5a96330a4a7c6",
+        true
+    )]
+    #[case(
+        "1b2a2b7e5b2be9a2a0ac40bd24ded0149b52302e3fbf4a7c71a5a96330a4a7c",
+        false
+    )]
+    #[case("cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJ", false)]
+    fn test_parse_private_key(#[case] key: &str, #[case] expected: bool) {
+        assert_eq!(
+            parse_private_key(key, KeyFormat::Auto, Network::Testnet).is_ok(),
+            expected
+        )
+    }
+
+    #[rstest]
+    #[case(KeyFormat::Auto)]
+    #[case(KeyFormat::Hex)]
+    fn test_hex_private_key(#[case] private_key_format: KeyFormat) {
+        // The 32 bytes inside the sample WIF
+        let args = Args {
+            private_key: Some(
+                "233aad6bb28a67e1a22f0d808ac73abffa2fa1bca56fddfcae0e9bd1c242d6d6".into(),
+            ),
+            private_key_format,
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let mut wif_tx_builder = TxBuilder::<All>::new(&sample_args()).unwrap();
+
+        assert_eq!(tx_builder.public_key, wif_tx_builder.public_key);
+        assert_eq!(
+            Address::p2pkh(&tx_builder.public_key.unwrap(), Network::Testnet).to_string(),
+            "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx"
+        );
+        assert_eq!(
+            tx_builder
+                .create_without_sig()
+                .unwrap()
+                .sign()
+                .unwrap()
+                .build()
+                .unwrap()
+                .hex(),
+            wif_tx_builder
+                .create_without_sig()
+                .unwrap()
+                .sign()
+                .unwrap()
+                .build()
+                .unwrap()
+                .hex()
+        );
+    }
+
+    #[rstest]
+    fn test_private_key_sources() {
+        let wif = "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP";
+        let build = |mut args: Args| {
+            args.load_private_key().unwrap();
+            TxBuilder::<All>::new(&args)
+                .unwrap()
+                .create_without_sig()
+                .unwrap()
+                .sign()
+                .unwrap()
+                .build()
+                .unwrap()
+                .hex()
+        };
+        let inline = build(sample_args());
+
+        let name = format!("GENERATE_BTC_TRANSACTION_KEY_{}", std::process::id());
+        std::env::set_var(&name, wif);
+        let from_env = build(Args {
+            private_key: None,
+            private_key_env: Some(name.clone()),
+            ..sample_args()
+        });
+        std::env::remove_var(&name);
+
+        let path = std::env::temp_dir().join(format!(
+            "generate-btc-transaction-key-{}.wif",
+            std::process::id()
+        ));
+        std::fs::write(&path, format!("{}\n", wif)).unwrap();
+        let from_file = build(Args {
+            private_key: None,
+            private_key_file: Some(path.clone()),
+            ..sample_args()
+        });
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(from_env, inline);
+        assert_eq!(from_file, inline);
+
+        // Both gone by now
+        let mut args = Args {
+            private_key: None,
+            private_key_env: Some(name.clone()),
+            ..sample_args()
+        };
+        assert_eq!(
+            args.load_private_key().unwrap_err().to_string(),
+            format!("`--private-key-env` {} is not set", name)
+        );
+        args.private_key_env = None;
+        args.private_key_file = Some(path);
+        assert!(args.load_private_key().is_err());
+    }
+
+    #[rstest]
+    fn test_hex_private_key_as_wif() {
+        let args = Args {
+            private_key: Some(
+                "233aad6bb28a67e1a22f0d808ac73abffa2fa1bca56fddfcae0e9bd1c242d6d6".into(),
+            ),
+            private_key_format: KeyFormat::Wif,
+            ..sample_args()
+        };
+
+        assert!(TxBuilder::<All>::new(&args)
+            .err()
+            .unwrap()
+            .to_string()
+            .starts_with("`--private-key` is not a valid WIF private key"));
+    }
+
+    #[rstest]
+    #[case(
+        Args {
+            private_key: Some("cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJ".into()),
+            ..sample_args()
+        },
+        |e: &TxError| matches!(e, TxError::InvalidPrivateKey { format: "WIF", .. })
+    )]
+    #[case(
+        Args {
+            utxo_txid: Some("d73ebea9".to_string()),
+            ..sample_args()
+        },
+        |e: &TxError| matches!(e, TxError::InvalidTxid { input_index: 0, .. })
+    )]
+    // Change of 500 satoshi is below the P2PKH dust threshold (546 satoshi)
+    #[case(
+        Args {
+            utxo_amount: Some(1_600),
+            dust_policy: DustPolicy::Fail,
+            ..sample_args()
+        },
+        |e: &TxError| matches!(
+            e,
+            TxError::DustOutput { amount: 500, threshold: 546, .. }
+        )
+    )]
+    #[case(
+        Args {
+            source_address: "n1S9T9p9WJss8d1eQsheokZjGfAQyEtchg".to_string(),
+            utxo_script_pubkey: Some("0014da79bfc8bc2845ff4c4ab2f846dbe8bdd3e41622".to_string()),
+            uncompressed: true,
+            ..sample_args()
+        },
+        |e: &TxError| matches!(e, TxError::SigningFailed { input_index: 0, .. })
+    )]
+    fn test_tx_error(#[case] args: Args, #[case] expected: fn(&TxError) -> bool) {
+        let result = TxBuilder::<All>::new(&args).and_then(|mut tx_builder| {
+            tx_builder.create_without_sig()?.sign()?;
+            Ok(())
+        });
+
+        assert!(expected(&result.unwrap_err()));
+    }
+
+    #[rstest]
+    #[case(false, "version,input count,input 0 txid,input 0 vout,input 0 scriptSig length,input 0 scriptSig,input 0 sequence,output count,output 0 value,output 0 scriptPubKey length,output 0 scriptPubKey,output 1 value,output 1 scriptPubKey length,output 1 scriptPubKey,locktime")]
+    #[case(true, "version,segwit marker,segwit flag,input count,input 0 txid,input 0 vout,input 0 scriptSig length,input 0 sequence,output count,output 0 value,output 0 scriptPubKey length,output 0 scriptPubKey,output 1 value,output 1 scriptPubKey length,output 1 scriptPubKey,input 0 witness item count,input 0 witness item 0 length,input 0 witness item 0,input 0 witness item 1 length,locktime")]
+    fn test_annotated(#[case] witness: bool, #[case] expected_labels: &str) {
+        let mut tx = TxBuilder::<All>::new(&sample_args())
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap();
+        if witness {
+            tx.0.input[0].script_sig = ScriptBuf::new();
+            tx.0.input[0].witness = Witness::from_slice(&[vec![0xab; 64], vec![]]);
+        }
+        let fields = tx.fields();
+
+        assert_eq!(
+            fields
+                .iter()
+                .map(|(label, _)| label.as_str())
+                .collect::<Vec<_>>(),
+            expected_labels.split(',').collect::<Vec<_>>()
+        );
+        assert_eq!(
+            fields
+                .into_iter()
+                .flat_map(|(_, bytes)| bytes)
+                .collect::<Vec<_>>(),
+            serialize(&tx.0)
+        );
+        let annotated = tx.annotated(Network::Testnet);
+        assert!(annotated.starts_with("version                                 01000000\n"));
+        assert!(annotated.contains(
+            "76a914a997f6d478624028ea1f36082e7ceb5d79d7567188ac  (mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn)\n"
+        ));
+    }
+
+    #[rstest]
+    fn test_sign_malformed_script_pubkey() {
+        let args = Args {
+            // OP_DUP OP_HASH160 announcing a 20-byte push with only 10 bytes left
+            utxo_script_pubkey: Some("76a9143d927250d4a4744f5f99".to_string()),
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let error = tx_builder
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .map(|_| ())
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            TxError::SighashComputation { input_index: 0, reason }
+                if reason == "malformed scriptPubKey (unexpected end of script)"
+        ));
+    }
+
+    #[rstest]
+    #[case("SIGHASH_ALL", Ok(vec![0x01, 0x81, 0x01]))]
+    #[case("SIGHASH_NONE|SIGHASH_ANYONECANPAY", Ok(vec![0x01, 0x81, 0x82]))]
+    #[case("none", Ok(vec![0x01, 0x81, 0x02]))]
+    // Only the destination and the change output
+    #[case(
+        "SIGHASH_SINGLE",
+        Err("cannot compute the sighash of input 2: SIGHASH_SINGLE needs an output at the same index")
+    )]
+    #[case("SIGHASH_DEFAULT", Err("invalid sighash type for input 2"))]
+    fn test_sighash_types(#[case] sighash: &str, #[case] expected: Result<Vec<u8>, &str>) {
+        let prevout = |vout: u32, sighash: Option<&str>| {
+            format!(
+                r#"{{"txid": "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331", "vout": {}, "amount": 4000, "script_pubkey": "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac"{}}}"#,
+                vout,
+                sighash.map_or(String::new(), |s| format!(r#", "sighash": "{}""#, s))
+            )
+        };
+        let args = Args {
+            utxo_txid: None,
+            utxo_tx_index: None,
+            utxo_amount: None,
+            utxo_script_pubkey: None,
+            prevouts: Some(
+                format!(
+                    "[{}, {}, {}]",
+                    prevout(0, None),
+                    prevout(1, Some("SIGHASH_ALL|SIGHASH_ANYONECANPAY")),
+                    prevout(2, Some(sighash))
+                )
+                .into(),
+            ),
+            ..sample_args()
+        };
+        let result = TxBuilder::<All>::new(&args).and_then(|mut tx_builder| {
+            Ok(tx_builder.create_without_sig()?.sign()?.build().unwrap())
+        });
+
+        // The sighash byte ends the signature, the first push of each script_sig
+        let flags = result.map(|tx| {
+            tx.0.input
+                .iter()
+                .map(|input| match input.script_sig.instructions().next() {
+                    Some(Ok(Instruction::PushBytes(bytes))) => *bytes.as_bytes().last().unwrap(),
+                    _ => panic!("unexpected script_sig"),
+                })
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(
+            flags.map_err(|e| e.to_string()),
+            expected.map_err(str::to_string)
+        );
+    }
+
+    #[rstest]
+    fn test_sign_multiple_utxos() {
+        let utxo = |vout: u32, amount: u64| Prevout {
+            txid: "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331".to_string(),
+            vout,
+            amount,
+            script_pubkey: "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string(),
+            sighash: None,
+            private_key: None,
+        };
+        let args = Args {
+            utxo_txid: None,
+            utxo_tx_index: None,
+            utxo_amount: None,
+            utxo_script_pubkey: None,
+            utxo: vec![utxo(0, 3_000), utxo(1, 2_000)],
+            send_amount: Some(3_000),
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let tx = tx_builder
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(tx.0.input.len(), 2);
+        assert!(tx.0.input.iter().all(|input| !input.script_sig.is_empty()));
+        // Each input signs its own sighash
+        assert_ne!(tx.0.input[0].script_sig, tx.0.input[1].script_sig);
+        // 5_000 - 3_000 - 1_000 fee
+        assert_eq!(tx.0.output[1].value, 1_000);
+    }
+
+    #[rstest]
+    #[case("SIGHASH_SINGLE", 0x03)]
+    #[case("single|anyonecanpay", 0x83)]
+    #[case("None | AnyoneCanPay", 0x82)]
+    fn test_sighash_short_names(#[case] sighash: &str, #[case] expected: u8) {
+        let args = Args {
+            sighash: Some(sighash.to_string()),
+            ..sample_args()
+        };
+        let tx = TxBuilder::<All>::new(&args)
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap();
+        let script_sig = &tx.0.input[0].script_sig;
+        let Some(Ok(Instruction::PushBytes(signature))) = script_sig.instructions().next() else {
+            panic!("unexpected script_sig")
+        };
+        // The length byte counts the DER signature and exactly one sighash byte
+        assert_eq!(script_sig.as_bytes()[0] as usize, signature.len());
+        assert_eq!(*signature.as_bytes().last().unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac", false, true)]
+    #[case("00143d927250d4a4744f5f99b499f750d85054dbf9fc", true, true)]
+    #[case(
+        "5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c",
+        true,
+        false
+    )]
+    fn test_psbt(
+        #[case] utxo_script_pubkey: &str,
+        #[case] witness_utxo: bool,
+        #[case] non_witness_utxo: bool,
+    ) {
+        // For the previous transaction to have the spent output at vout 1
+        let prev_tx = Transaction {
+            version: 1,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: 0,
+                    script_pubkey: ScriptBuf::new(),
+                },
+                TxOut {
+                    value: 4847873,
+                    script_pubkey: ScriptBuf::from_hex(utxo_script_pubkey).unwrap(),
+                },
+            ],
+        };
+        let args = Args {
+            private_key: None,
+            utxo_txid: Some(prev_tx.txid().to_string()),
+            utxo_script_pubkey: Some(utxo_script_pubkey.to_string()),
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let tx = tx_builder.create_without_sig().unwrap().build().unwrap();
+        let psbt = tx_builder.psbt(|_| Ok(Some(prev_tx.clone()))).unwrap();
+
+        let decoded = Psbt::deserialize(&psbt.serialize()).unwrap();
+        assert_eq!(decoded.unsigned_tx, tx.0);
+        assert_eq!(decoded.inputs.len(), 1);
+        assert_eq!(decoded.outputs.len(), 2);
+        assert_eq!(decoded.inputs[0].witness_utxo.is_some(), witness_utxo);
+        assert_eq!(
+            decoded.inputs[0].non_witness_utxo.is_some(),
+            non_witness_utxo
+        );
+        // Without a key there is nothing to sign with
+        assert!(matches!(
+            tx_builder.sign().map(|_| ()).unwrap_err(),
+            TxError::SigningFailed { input_index: 0, reason } if reason == "no private key given"
+        ));
+    }
+
+    #[rstest]
+    fn test_verification_bundle() {
+        let mut tx_builder = TxBuilder::<All>::new(&sample_args()).unwrap();
+        let tx = tx_builder
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap();
+        // Round-trip through the JSON text, as a reviewer would receive it
+        let bundle = Value::parse(&tx_builder.verification_bundle().unwrap().to_string()).unwrap();
+        let field =
+            |value: &Value, key: &str| value.get(key).unwrap().as_str().unwrap().to_string();
+        let input = &bundle.get("inputs").unwrap().as_array().unwrap()[0];
+
+        assert_eq!(field(&bundle, "signed_transaction"), tx.hex());
+        assert_eq!(input.get("amount").unwrap().as_u64(), Some(4847873));
+        assert_eq!(field(input, "sighash_type"), "SIGHASH_ALL");
+
+        // Verify the signature from the bundle alone
+        let unsigned = decode_transaction(&field(&bundle, "unsigned_transaction")).unwrap();
+        assert!(unsigned.input[0].script_sig.is_empty());
+        let script_pubkey = ScriptBuf::from_hex(&field(input, "script_pubkey")).unwrap();
+        let sighash = SighashCache::new(&unsigned)
+            .legacy_signature_hash(0, &script_pubkey, EcdsaSighashType::All.to_u32())
+            .unwrap();
+        assert_eq!(field(input, "sighash"), sighash.to_string());
+        let signature = Vec::<u8>::from_hex(&field(input, "signature")).unwrap();
+        let (sighash_byte, der) = signature.split_last().unwrap();
+        assert_eq!(*sighash_byte, EcdsaSighashType::All.to_u32() as u8);
+        let public_key = PublicKey::from_str(&field(input, "public_key")).unwrap();
+        assert!(tx_builder
+            .secp
+            .verify_ecdsa(
+                &secp256k1::Message::from_slice(&sighash[..]).unwrap(),
+                &Signature::from_der(der).unwrap(),
+                &public_key.inner
+            )
+            .is_ok());
+    }
+
+    #[rstest]
+    #[case("76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac")]
+    #[case("00143d927250d4a4744f5f99b499f750d85054dbf9fc")]
+    fn test_verify(#[case] script_pubkey: &str) {
+        let args = Args {
+            utxo_script_pubkey: Some(script_pubkey.to_string()),
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        tx_builder.create_without_sig().unwrap().sign().unwrap();
+        assert!(tx_builder.verify().is_ok());
+
+        // Flip a bit of the signature's r value, after the DER headers
+        let input = &mut tx_builder.transaction.as_mut().unwrap().input[0];
+        if input.witness.is_empty() {
+            let mut script_sig = input.script_sig.to_bytes();
+            script_sig[10] ^= 1;
+            input.script_sig = ScriptBuf::from(script_sig);
+        } else {
+            let mut witness = input.witness.to_vec();
+            witness[0][9] ^= 1;
+            input.witness = Witness::from_slice(&witness);
+        }
+        assert_eq!(
+            tx_builder.verify().err().unwrap().to_string(),
+            format!(
+                "cannot sign input 0: signature does not verify against scriptPubKey {}: \
+                 the signature does not match the sighash",
+                script_pubkey
+            )
+        );
+    }
+
+    #[rstest]
+    #[case("76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac", "SIGHASH_ALL")]
+    #[case("00143d927250d4a4744f5f99b499f750d85054dbf9fc", "SIGHASH_SINGLE")]
+    // P2SH-P2WPKH, whose redeem script comes from the script_sig
+    #[case("a9140b18ea6f1de6ef6513e23971bab139a5c09170bc87", "SIGHASH_ALL")]
+    fn test_verify_signatures(#[case] script_pubkey: &str, #[case] sighash: &str) {
+        let args = Args {
+            utxo_script_pubkey: Some(script_pubkey.to_string()),
+            sighash: Some(sighash.to_string()),
+            ..sample_args()
+        };
+        let hex = TxBuilder::<All>::new(&args)
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap()
+            .hex();
+        let prevout = Prevout::from_str(&format!(
+            "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331:1:4847873:{}",
+            script_pubkey
+        ))
+        .unwrap();
+        assert!(Tx::decode(&hex)
+            .unwrap()
+            .verify_signatures(std::slice::from_ref(&prevout))
+            .is_ok());
+
+        // Another amount changes the BIP143 sighash, a corrupted signature any sighash
+        let mut tx = Tx::decode(&hex).unwrap();
+        let input = &mut tx.0.input[0];
+        if input.witness.is_empty() {
+            let mut script_sig = input.script_sig.to_bytes();
+            script_sig[10] ^= 1;
+            input.script_sig = ScriptBuf::from(script_sig);
+        } else {
+            let mut witness = input.witness.to_vec();
+            witness[0][9] ^= 1;
+            input.witness = Witness::from_slice(&witness);
+        }
+        assert!(tx
+            .verify_signatures(std::slice::from_ref(&prevout))
+            .is_err());
+        if !script_pubkey.starts_with("76a9") {
+            let prevout = Prevout {
+                amount: 4_847_872,
+                ..prevout.clone()
+            };
+            assert!(Tx::decode(&hex)
+                .unwrap()
+                .verify_signatures(&[prevout])
+                .is_err());
+        }
+        assert_eq!(
+            Tx::decode(&hex)
+                .unwrap()
+                .verify_signatures(&[prevout.clone(), prevout])
+                .unwrap_err()
+                .to_string(),
+            "the transaction has 1 inputs, but 2 prevouts are given"
+        );
+    }
+
+    #[rstest]
+    fn test_sign_erases_keys() {
+        let mut tx_builder = TxBuilder::<All>::new(&sample_args()).unwrap();
+        let private_key = tx_builder.keys[0];
+        tx_builder.create_without_sig().unwrap().sign().unwrap();
+
+        assert_ne!(tx_builder.keys[0].inner, private_key.inner);
+        assert_eq!(tx_builder.key_network(), Network::Testnet);
+        tx_builder.verify().unwrap();
+    }
+
+    #[rstest]
+    #[case(false, 33)]
+    #[case(true, 65)]
+    fn test_uncompressed(#[case] uncompressed: bool, #[case] expected: usize) {
+        let args = Args {
+            source_address: match uncompressed {
+                true => "n1S9T9p9WJss8d1eQsheokZjGfAQyEtchg".to_string(),
+                false => "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string(),
+            },
+            utxo_script_pubkey: Some(match uncompressed {
+                true => "76a914da79bfc8bc2845ff4c4ab2f846dbe8bdd3e4162288ac".to_string(),
+                false => "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string(),
+            }),
+            uncompressed,
+            ..sample_args()
+        };
+        let tx = TxBuilder::<All>::new(&args)
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let public_key = tx.0.input[0]
+            .script_sig
+            .instructions()
+            .filter_map(|instruction| match instruction {
+                Ok(Instruction::PushBytes(bytes)) => Some(bytes.len()),
+                _ => None,
+            })
+            .nth(1);
+        assert_eq!(public_key, Some(expected));
+    }
+
+    #[rstest]
+    fn test_sign_p2wpkh() {
+        let script_pubkey = "00143d927250d4a4744f5f99b499f750d85054dbf9fc";
+        let args = Args {
+            utxo_script_pubkey: Some(script_pubkey.to_string()),
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let tx = tx_builder
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let witness = &tx.0.input[0].witness;
+        assert!(tx.0.input[0].script_sig.is_empty());
+        assert_eq!(witness.len(), 2);
+        let public_key = PublicKey::from_slice(&witness[1]).unwrap();
+        assert_eq!(Some(public_key), tx_builder.public_key);
+
+        let (der, sighash_byte) = witness[0].split_at(witness[0].len() - 1);
+        assert_eq!(sighash_byte, [EcdsaSighashType::All as u8]);
+        let script_code = ScriptBuf::from_hex(script_pubkey)
+            .unwrap()
+            .p2wpkh_script_code()
+            .unwrap();
+        let sighash = SighashCache::new(&tx.0)
+            .segwit_signature_hash(0, &script_code, 4847873, EcdsaSighashType::All)
+            .unwrap();
+        assert!(tx_builder
+            .secp
+            .verify_ecdsa(
+                &secp256k1::Message::from_slice(&sighash[..]).unwrap(),
+                &Signature::from_der(der).unwrap(),
+                &public_key.inner
+            )
+            .is_ok());
+    }
+
+    #[rstest]
+    fn test_log_level_output() {
+        let build = |level: Level| {
+            log::init(level);
+            TxBuilder::<All>::new(&sample_args())
+                .unwrap()
+                .create_without_sig()
+                .unwrap()
+                .sign()
+                .unwrap()
+                .build()
+                .unwrap()
+                .hex()
+        };
+        let quiet = build(Level::Warn);
+        let verbose = build(Level::Trace);
+        log::init(Level::Warn);
+
+        assert_eq!(quiet, verbose);
+    }
+
+    #[rstest]
+    fn test_sign_taproot() {
+        // BIP86 test vector: m/86'/0'/0'/0/0 of the "abandon ... about" mnemonic
+        let output_key = "a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c";
+        let args = Args {
+            source_address: "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+                .to_string(),
+            destination_address: Some(
+                "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr".to_string(),
+            ),
+            private_key: Some("KyRv5iFPHG7iB5E4CqvMzH3WFJVhbfYK4VY7XAedd9Ys69mEsPLQ".into()),
+            utxo_script_pubkey: Some(format!("5120{}", output_key)),
+            ..sample_args()
+        };
+        let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let tx = tx_builder
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap();
+
+        // The witness survives serialization
+        assert_eq!(decode_transaction(&tx.hex()).unwrap(), tx.0);
+        assert!(tx_builder.verify().is_ok());
+
+        let witness = &tx.0.input[0].witness;
+        assert!(tx.0.input[0].script_sig.is_empty());
+        assert_eq!(witness.len(), 1);
+        // SIGHASH_DEFAULT signatures carry no sighash byte
+        let signature = secp256k1::schnorr::Signature::from_slice(&witness[0]).unwrap();
+
+        let prevouts = [TxOut {
+            value: 4847873,
+            script_pubkey: tx_builder.utxos[0].script_pubkey.clone(),
+        }];
+        let sighash = SighashCache::new(&tx.0)
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), TapSighashType::Default)
+            .unwrap();
+        let message = secp256k1::Message::from_slice(&sighash[..]).unwrap();
+        let output_key =
+            secp256k1::XOnlyPublicKey::from_slice(&Vec::<u8>::from_hex(output_key).unwrap())
+                .unwrap();
+        assert!(tx_builder
+            .secp
+            .verify_schnorr(&signature, &message, &output_key)
+            .is_ok());
+
+        let bundle = tx_builder.verification_bundle().unwrap();
+        let input = &bundle.get("inputs").unwrap().as_array().unwrap()[0];
+        assert_eq!(
+            input.get("sighash_type").unwrap().as_str(),
+            Some("SIGHASH_DEFAULT")
+        );
+        assert_eq!(
+            input.get("sighash").unwrap().as_str(),
+            Some(sighash.to_string().as_str())
+        );
+        assert_eq!(
+            input.get("signature").unwrap().as_str(),
+            Some(hex::encode(&witness[0]).as_str())
+        );
+        assert_eq!(input.get("public_key"), Some(&Value::Null));
+    }
+
+    #[rstest]
+    // ECDSA Signature: 70-72 bytes
+    // SIGHASH_ALL: 1 byte
+    // Public Key: 33 bytes or 65 bytes
+    // MIN: 70 + 1 + 33 = 104
+    #[case(prepare_test_create_script_sig(1), 104)]
+    #[case(prepare_test_create_script_sig(2), 104)]
+    #[case(prepare_test_create_script_sig(3), 104)]
+    fn test_create_script_sig(
+        #[case] params: (Signature, PublicKey),
+        #[case] expected_min_len: usize,
+    ) {
+        assert!(
+            expected_min_len
+                <= TxBuilder::<All>::create_script_sig(&params.0, &params.1, EcdsaSighashType::All)
+                    .len()
+        )
+    }
+
+    #[rstest]
+    #[case(EcdsaSighashType::All)]
+    #[case(EcdsaSighashType::None)]
+    #[case(EcdsaSighashType::SinglePlusAnyoneCanPay)]
+    fn test_create_script_sig_push_len(#[case] sighash_type: EcdsaSighashType) {
+        let (signature, public_key) = prepare_test_create_script_sig(1);
+        let der = signature.serialize_der();
+        let script_sig = TxBuilder::<All>::create_script_sig(&signature, &public_key, sighash_type);
+
+        assert_eq!(script_sig[0] as usize, der.len() + 1);
+        assert_eq!(&script_sig[1..=der.len()], &der[..]);
+        assert_eq!(script_sig[der.len() + 1], sighash_type.to_u32() as u8);
+        assert_eq!(script_sig[der.len() + 2], 33);
+        assert_eq!(script_sig.len(), der.len() + 2 + 1 + 33);
+    }
+
+    #[rstest]
+    // 10 + 2900 * 34 = 98_610 vbytes
+    #[case(2900, false)]
+    // 10 + 3000 * 34 = 102_010 vbytes
+    #[case(3000, true)]
+    fn test_standardness_error(#[case] output_count: usize, #[case] expected: bool) {
+        let mut tx = TxBuilder::<All>::new(&sample_args())
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .build()
+            .unwrap();
+        tx.0.input.clear();
+        tx.0.output = vec![tx.0.output[0].clone(); output_count];
+
+        assert_eq!(tx.standardness_error().is_some(), expected)
+    }
+
+    #[rstest]
+    #[case(0, Some(2_430_000), 1_687_500_000, false)]
+    #[case(2_430_000, Some(2_430_000), 1_687_500_000, false)]
+    #[case(2_430_001, Some(2_430_000), 1_687_500_000, true)]
+    #[case(2_430_001, None, 1_687_500_000, false)]
+    #[case(1_687_499_999, Some(2_430_000), 1_687_500_000, false)]
+    #[case(1_687_500_000, None, 1_687_500_000, true)]
+    fn test_lock_time_warning(
+        #[case] lock_time: u32,
+        #[case] tip_height: Option<u32>,
+        #[case] tip_time: u32,
+        #[case] expected: bool,
+    ) {
+        let args = Args {
+            lock_time,
+            ..sample_args()
+        };
+        let tx = TxBuilder::<All>::new(&args)
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(
+            tx.lock_time_warning(tip_height, tip_time).is_some(),
+            expected
+        )
+    }
+
+    #[rstest]
+    #[case(None, vec![true, true, true])]
+    #[case(Some(vec![0, 2]), vec![true, false, true])]
+    #[case(Some(vec![1]), vec![false, true, false])]
+    fn test_sign_inputs(#[case] sign_inputs: Option<Vec<usize>>, #[case] expected: Vec<bool>) {
+        let prevout = r#"{"txid": "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331", "vout": 1, "amount": 4847873, "script_pubkey": "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac"}"#;
+        let args = Args {
+            utxo_txid: None,
+            utxo_tx_index: None,
+            utxo_amount: None,
+            utxo_script_pubkey: None,
+            prevouts: Some(format!("[{}, {}, {}]", prevout, prevout, prevout).into()),
+            sign_inputs,
+            ..sample_args()
+        };
+        let tx = TxBuilder::<All>::new(&args)
+            .unwrap()
+            .create_without_sig()
+            .unwrap()
+            .sign()
+            .unwrap()
+            .build()
+            .unwrap();
+        let signed: Vec<bool> =
+            tx.0.input
+                .iter()
+                .map(|i| !i.script_sig.is_empty())
+                .collect();
+        assert_eq!(signed, expected)
+    }
+
+    #[rstest]
+    fn test_utxo_amounts() {
+        let args = Args {
+            utxo_txid: None,
+            utxo_tx_index: None,
+            utxo_amount: None,
+            utxo_script_pubkey: None,
+            prevouts: Some(
+                r#"[
+                    {"txid": "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331", "vout": 1, "amount": 4000, "script_pubkey": "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac"},
+                    {"txid": "2a4bb3ed0533d9e1d4c896003e4ed0a1378e128c984632a0080b491ba316f3eb", "vout": 0, "amount": 6000, "script_pubkey": "00143d927250d4a4744f5f99b499f750d85054dbf9fc"}
+                ]"#
+                .to_string().into()
+            ),
+            ..sample_args()
+        };
+        let tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        let utxos: Vec<(String, u64)> = tx_builder
+            .utxos
+            .iter()
+            .map(|utxo| (utxo.script_pubkey.to_hex_string(), utxo.amount))
+            .collect();
+
+        assert_eq!(
+            utxos,
+            vec![
+                (
+                    "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string(),
+                    4000
+                ),
+                (
+                    "00143d927250d4a4744f5f99b499f750d85054dbf9fc".to_string(),
+                    6000
+                ),
+            ]
+        );
+        assert_eq!(tx_builder.calc_change_amount().unwrap(), 10_000 - 100 - FEE);
+    }
+
+    fn sample_args() -> Args {
+        Args {
+            source_address: "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string(),
+            destination_address: Some("mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string()),
+            private_key: Some("cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP".into()),
+            send_amount: Some(100),
+            utxo_txid: Some(
+                "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331".to_string(),
+            ),
+            utxo_tx_index: Some(1),
+            utxo_amount: Some(4847873),
+            utxo_script_pubkey: Some(
+                "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string(),
+            ),
+            ..Default::default()
+        }
     }
 
     fn prepare_test_create_script_sig(seed: u64) -> (Signature, PublicKey) {
@@ -267,32 +5437,33 @@ mod tests {
 
         let args = Args {
             source_address: "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string(),
-            destination_address: "mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string(),
-            private_key: "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP".to_string(),
-            send_amount: rng.gen_range(100..1000),
-            utxo_txid: random_string(&mut rng, 64, hexadecimal_chars),
-            utxo_tx_index: rng.gen::<u32>(),
-            utxo_amount: rng.gen_range(5000..20000),
-            utxo_script_pubkey: random_string(&mut rng, 50, hexadecimal_chars),
+            destination_address: Some("mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string()),
+            private_key: Some("cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP".into()),
+            send_amount: Some(rng.gen_range(100..1000)),
+            utxo_txid: Some(random_string(&mut rng, 64, hexadecimal_chars)),
+            utxo_tx_index: Some(rng.gen::<u32>()),
+            utxo_amount: Some(rng.gen_range(5000..20000)),
+            utxo_script_pubkey: Some(random_string(&mut rng, 50, hexadecimal_chars)),
+            ..Default::default()
         };
 
         let mut tx_builder = TxBuilder::<All>::new(&args).unwrap();
+        // Random scriptPubKeys are often malformed, which `sign` refuses, so sign by hand
         let tx_builder = tx_builder.create_without_sig().unwrap();
-        let tx_builder = tx_builder.sign().unwrap();
 
-        let private_key = tx_builder.private_key;
+        let private_key = tx_builder.keys[0];
         let public_key = private_key.public_key(&tx_builder.secp);
 
         let transaction = tx_builder.transaction.as_ref().unwrap();
         let sighash = SighashCache::new(transaction)
             .legacy_signature_hash(
-                INPUT_INDEX,
-                &tx_builder.utxo_script_pubkey,
-                SIGHASH_ALL as u32,
+                0,
+                &tx_builder.utxos[0].script_pubkey,
+                EcdsaSighashType::All.to_u32(),
             )
             .unwrap();
         let message = secp256k1::Message::from_slice(&sighash[..]).unwrap();
-        let secret_key = SecretKey::from_slice(&private_key.to_bytes()).unwrap();
+        let secret_key = private_key.inner;
         let signature = tx_builder.secp.sign_ecdsa(&message, &secret_key);
 
         (signature, public_key)
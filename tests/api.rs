@@ -0,0 +1,49 @@
+use bitcoin::secp256k1::All;
+use generate_btc_transaction::{Prevout, TxBuilder, TxParams};
+use rstest::*;
+
+fn sample_params() -> TxParams {
+    TxParams {
+        source_address: "mm8Wx3H3b3est26kxN1XY6sTnYNkxX16Lx".to_string(),
+        destination_address: "mvygY8USGWGp3pnTRzfgWPzoaarZ9q74gn".to_string(),
+        private_key: "cNmBYajCpAPzGL4VdxjM3qUGWpeasGu2RSAk5QjHnujZVVRuDLJP".into(),
+        send_amount: 100,
+        utxos: vec![Prevout {
+            txid: "d73ebea9ad590316b5fbae5a176937178cdba72c1422a1636817a8f864a9c331".to_string(),
+            vout: 1,
+            amount: 4847873,
+            script_pubkey: "76a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac".to_string(),
+            sighash: None,
+            private_key: None,
+        }],
+        ..Default::default()
+    }
+}
+
+#[rstest]
+fn test_build_and_sign() {
+    let tx = TxBuilder::<All>::from_params(&sample_params())
+        .unwrap()
+        .create_without_sig()
+        .unwrap()
+        .sign()
+        .unwrap()
+        .verify()
+        .unwrap()
+        .build()
+        .unwrap();
+
+    // The same transaction as `inputs/exec.sh` prints
+    assert_eq!(tx.output(), "0x010000000131c3a964f8a8176863a122142ca7db8c173769175aaefbb5160359ada9be3ed7010000006a4730440220644f271a0da965c8a68db74c51f1f66be93f3ea51fb9c443dd20d4857637de3402203c736aafa921dd44ab01360fcf0dbe7443fdad8fec95751be5732211294c5ac701210303998660a6a026b2f8aa72d37a077b6a76b282b2d5b73fc582fdc274f66fa5bcffffffff0264000000000000001976a914a997f6d478624028ea1f36082e7ceb5d79d7567188acb5f44900000000001976a9143d927250d4a4744f5f99b499f750d85054dbf9fc88ac00000000");
+}
+
+#[rstest]
+fn test_fee_and_fee_rate() {
+    let params = TxParams {
+        fee: Some(500),
+        fee_rate: Some(2.0),
+        ..sample_params()
+    };
+
+    assert!(TxBuilder::<All>::from_params(&params).is_err());
+}